@@ -0,0 +1,23 @@
+// Times type checking the generated programs from steiner::bench at their default sizes.
+// `cargo bench -- let-duplication` only runs the benchmarks with that in their name
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use steiner::bench::BENCHMARKS;
+use steiner::type_checker::type_::{get_type_and_notes_in, TypeContext};
+
+fn inference(criterion: &mut Criterion) {
+    for benchmark in BENCHMARKS {
+        let program = (benchmark.program)(benchmark.size);
+
+        // Generating and cloning the program isn't part of the time
+        criterion.bench_function(benchmark.name, |bencher| {
+            bencher.iter_batched(
+                || program.clone(),
+                |program| get_type_and_notes_in(TypeContext::new(), program),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
+criterion_group!(benches, inference);
+criterion_main!(benches);
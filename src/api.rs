@@ -0,0 +1,56 @@
+// The entry points for programs embedding steiner. Everything else can change
+// between versions, while these only grow
+use crate::diagnostic::{check_source, Diagnostic};
+use crate::messages::Locale;
+use crate::parser::parse_expression;
+use crate::type_checker::type_::{get_typed_ast_in, Type, TypeCheckOptions, TypeContext};
+use crate::type_checker::typed_ast::TypedAst;
+
+// A program which type checked
+#[derive(Debug, Clone)]
+pub struct TypedProgram {
+    // The type of the whole program, with quantifiers named a, b, c...
+    pub ty: Type,
+    // Every expression of the program, together with its type
+    pub ast: TypedAst,
+    // Things worth telling the user even though nothing is wrong, like what holes stand for
+    pub notes: Vec<Diagnostic>,
+}
+
+// Parse and type check a program with the prelude in scope
+pub fn compile(source: &str) -> Result<TypedProgram, Vec<Diagnostic>> {
+    compile_with(source, TypeCheckOptions::default())
+}
+
+pub fn compile_with(
+    source: &str,
+    options: TypeCheckOptions,
+) -> Result<TypedProgram, Vec<Diagnostic>> {
+    compile_in(source, TypeContext::new().with_options(options))
+}
+
+// Compile in a context set up by the host, like one from TypeContext::builder
+pub fn compile_in(source: &str, context: TypeContext) -> Result<TypedProgram, Vec<Diagnostic>> {
+    let (ty, diagnostics) = check_source(source, context.clone(), Locale::default());
+
+    let ty = match ty {
+        Some(ty) => ty,
+        None => return Err(diagnostics),
+    };
+
+    // Neither of these can fail after the program checked successfully
+    let ast = parse_expression(source).map_err(|_| diagnostics.clone())?;
+    let ast =
+        get_typed_ast_in(context.with_eager_solving(), ast).map_err(|_| diagnostics.clone())?;
+
+    Ok(TypedProgram {
+        ty,
+        ast,
+        notes: diagnostics,
+    })
+}
+
+// Only the type of a program
+pub fn infer_type(source: &str) -> Result<Type, Vec<Diagnostic>> {
+    compile(source).map(|program| program.ty)
+}
@@ -0,0 +1,109 @@
+// Synthetic programs for measuring how fast type checking is. `steiner bench` and
+// benches/inference.rs both time these, so numbers from either can be compared
+use crate::parser::Ast;
+
+pub struct Benchmark {
+    pub name: &'static str,
+    // What the size means depends on the program, so each one has its own default
+    pub size: usize,
+    pub program: fn(usize) -> Ast,
+}
+
+pub const BENCHMARKS: &[Benchmark] = &[
+    Benchmark {
+        name: "deep-lets",
+        size: 200,
+        program: deep_lets,
+    },
+    Benchmark {
+        name: "wide-application",
+        size: 200,
+        program: wide_application,
+    },
+    Benchmark {
+        name: "many-constraints",
+        size: 11,
+        program: many_constraints,
+    },
+    Benchmark {
+        name: "let-duplication",
+        size: 3,
+        program: let_duplication,
+    },
+];
+
+fn variable(name: &str) -> Ast {
+    Ast::Variable(name.to_string())
+}
+
+// let x0 = 1 in let x1 = add x0 1 in ... xsize
+pub fn deep_lets(size: usize) -> Ast {
+    let mut body = variable(&format!("x{}", size));
+
+    for index in (1..=size).rev() {
+        let value = variable("add").call_chain(vec![
+            variable(&format!("x{}", index - 1)),
+            Ast::FloatLiteral(1.0),
+        ]);
+        body = Ast::new_let(format!("x{}", index), value, body);
+    }
+
+    Ast::new_let("x0".to_string(), Ast::FloatLiteral(1.0), body)
+}
+
+// \f -> f 1 1 ... 1
+//
+// This grows about cubically with the size: every argument adds a binding for the type of f,
+// and merging it rewrites every binding found before, each as long as the chain so far.
+// Keeping the substitution in a union-find would take care of that
+pub fn wide_application(size: usize) -> Ast {
+    let arguments = (0..size).map(|_| Ast::FloatLiteral(1.0)).collect();
+
+    Ast::new_lambda("f".to_string(), variable("f").call_chain(arguments))
+}
+
+// add (add ... ) (add ...), adding up 2^size ones. The tree stays shallow, while the
+// constraints of every addition pile up for the solver
+pub fn many_constraints(size: usize) -> Ast {
+    if size == 0 {
+        Ast::FloatLiteral(1.0)
+    } else {
+        variable("add").call_chain(vec![many_constraints(size - 1), many_constraints(size - 1)])
+    }
+}
+
+// The classic worst case for let polymorphism:
+//
+//     let pair = \x -> \f -> f x x in
+//     let x1 = \y -> pair (pair y) in
+//     let x2 = \y -> x1 (x1 y) in
+//     ... xsize
+//
+// Every type is about the square of the one before it in size, so only small sizes finish
+pub fn let_duplication(size: usize) -> Ast {
+    let compose_twice = |function: &str| {
+        let inner = Ast::new_call(variable(function), variable("y"));
+        Ast::new_lambda("y".to_string(), Ast::new_call(variable(function), inner))
+    };
+
+    let mut body = variable(&format!("x{}", size));
+
+    for index in (2..=size).rev() {
+        let value = compose_twice(&format!("x{}", index - 1));
+        body = Ast::new_let(format!("x{}", index), value, body);
+    }
+
+    let pair = Ast::new_lambda(
+        "x".to_string(),
+        Ast::new_lambda(
+            "f".to_string(),
+            variable("f").call_chain(vec![variable("x"), variable("x")]),
+        ),
+    );
+
+    Ast::new_let(
+        "pair".to_string(),
+        pair,
+        Ast::new_let("x1".to_string(), compose_twice("pair"), body),
+    )
+}
@@ -0,0 +1,251 @@
+use crate::json::json_string;
+use crate::messages::Locale;
+use crate::parser::{parse_expression_recovering, Span, SyntaxError};
+use crate::plugin::Plugins;
+use crate::type_checker::type_::{
+    get_type_or_errors_in, get_typed_ast_in, LocatedError, Note, Type, TypeContext,
+};
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Note,
+}
+
+impl Severity {
+    fn name(self: Severity) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Note => "note",
+        }
+    }
+}
+
+// Something worth showing the user, in a shape tools can consume
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // Only type errors have codes so far
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Option<Span>,
+    // Extra lines of information, like suggestions
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn from_syntax_error(error: &SyntaxError) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            message: error.message.clone(),
+            span: Some(error.span),
+            notes: vec![],
+        }
+    }
+
+    pub fn from_type_error(error: &LocatedError, locale: Locale) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: Some(error.error.code()),
+            message: error.message(locale),
+            span: error.span,
+            notes: error.notes(locale),
+        }
+    }
+
+    pub fn from_note(note: &Note) -> Diagnostic {
+        let (span, message, notes) = match note {
+            Note::Wildcard(offset, ty) => (
+                Span::new(*offset, offset + 1),
+                format!("Wildcard stands for {}", ty),
+                vec![],
+            ),
+            Note::Hole(offset, name, ty, fits) => {
+                let name = match name {
+                    Some(name) => format!("?{}", name),
+                    None => "_".to_string(),
+                };
+                let fits = fits
+                    .iter()
+                    .map(|(name, ty)| format!("{} :: {} would fit", name, ty))
+                    .collect();
+
+                (
+                    Span::new(*offset, offset + name.len()),
+                    format!("Found hole {} of type {}", name, ty),
+                    fits,
+                )
+            }
+        };
+
+        Diagnostic {
+            severity: Severity::Note,
+            code: None,
+            message,
+            span: Some(span),
+            notes,
+        }
+    }
+
+    pub fn is_error(self: &Diagnostic) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // A single json object, written by hand since we don't depend on serde
+    pub fn to_json(self: &Diagnostic) -> String {
+        let code = match self.code {
+            Some(code) => json_string(code),
+            None => "null".to_string(),
+        };
+        let span = match self.span {
+            Some(span) => format!("{{\"start\":{},\"end\":{}}}", span.start, span.end),
+            None => "null".to_string(),
+        };
+        let notes: Vec<_> = self.notes.iter().map(|note| json_string(note)).collect();
+
+        format!(
+            "{{\"severity\":{},\"code\":{},\"message\":{},\"span\":{},\"notes\":[{}]}}",
+            json_string(self.severity.name()),
+            code,
+            json_string(&self.message),
+            span,
+            notes.join(",")
+        )
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(self: &Self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.severity.name())?;
+
+        if let Some(code) = self.code {
+            write!(f, "[{}]", code)?;
+        }
+
+        if let Some(span) = self.span {
+            write!(f, " at offsets {}-{}", span.start, span.end)?;
+        }
+
+        write!(f, ": {}", self.message)?;
+
+        for note in &self.notes {
+            write!(f, "\n    {}", note)?;
+        }
+
+        Ok(())
+    }
+}
+
+// A json array with every diagnostic in it
+pub fn to_json(diagnostics: &[Diagnostic]) -> String {
+    let objects: Vec<_> = diagnostics.iter().map(Diagnostic::to_json).collect();
+    format!("[{}]", objects.join(","))
+}
+
+// Parse and type check a program, collecting everything there is to say about it.
+// The type is only there when no errors were found
+pub fn check_source(
+    source: &str,
+    context: TypeContext,
+    locale: Locale,
+) -> (Option<Type>, Vec<Diagnostic>) {
+    check_source_with(source, context, locale, &Plugins::default())
+}
+
+// Same as check_source, also running the passes and lints of some plugins
+pub fn check_source_with(
+    source: &str,
+    context: TypeContext,
+    locale: Locale,
+    plugins: &Plugins,
+) -> (Option<Type>, Vec<Diagnostic>) {
+    let (ast, syntax_errors) = parse_expression_recovering(source);
+    let mut diagnostics: Vec<_> = syntax_errors
+        .iter()
+        .map(Diagnostic::from_syntax_error)
+        .collect();
+
+    let ast = match ast {
+        Some(ast) => plugins.run_passes(ast),
+        None => return (None, diagnostics),
+    };
+
+    // Solving while inferring lets errors know which expression they came from
+    let context = context.with_eager_solving();
+
+    match get_type_or_errors_in(context.clone(), ast.clone()) {
+        Ok((ty, notes)) => {
+            diagnostics.extend(notes.iter().map(Diagnostic::from_note));
+
+            // Lints want the typed tree, which we only have to build when there are any
+            if plugins.has_lints() {
+                if let Ok(typed) = get_typed_ast_in(context, ast) {
+                    diagnostics.extend(plugins.run_lints(&typed));
+                }
+            }
+
+            if syntax_errors.is_empty() {
+                (Some(ty), diagnostics)
+            } else {
+                (None, diagnostics)
+            }
+        }
+        Err(errors) => {
+            diagnostics.extend(
+                errors
+                    .iter()
+                    .map(|error| Diagnostic::from_type_error(error, locale)),
+            );
+
+            (None, diagnostics)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(source: &str) -> (Option<Type>, Vec<Diagnostic>) {
+        check_source(source, TypeContext::new(), Locale::English)
+    }
+
+    #[test]
+    fn type_errors_become_json() {
+        let (ty, diagnostics) = check("lenght");
+
+        assert!(ty.is_none());
+        assert_eq!(
+            to_json(&diagnostics),
+            "[{\"severity\":\"error\",\"code\":\"E0004\",\"message\":\"Variable lenght is not in scope\",\"span\":{\"start\":0,\"end\":6},\"notes\":[\"Did you mean length?\"]}]"
+        );
+    }
+
+    // Syntax errors don't stop the rest of the program from getting checked
+    #[test]
+    fn syntax_and_type_errors_get_reported_together() {
+        let (ty, diagnostics) = check("f (a $)");
+        let codes: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect();
+
+        assert!(ty.is_none());
+        assert_eq!(codes, vec![None, Some("E0004")]);
+        assert!(diagnostics.iter().all(Diagnostic::is_error));
+    }
+
+    // Notes don't keep the program from having a type
+    #[test]
+    fn notes_come_with_the_type() {
+        let (ty, diagnostics) = check("?h :: Boolean");
+
+        assert_eq!(ty.unwrap().to_string(), "Boolean");
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "note at offsets 0-2: Found hole ?h of type Boolean\n    false :: Boolean would fit\n    true :: Boolean would fit"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use crate::parser::{parse_comments, parse_expression, Comment};
+use crate::type_checker::type_::get_type_or_errors;
+
+// A fenced code block found in a comment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Example {
+    // Line the code starts on, counting from 1
+    pub line: usize,
+    pub code: String,
+}
+
+// The lines of a comment without the comment syntax, each with the line it's on
+fn comment_lines(source: &str, comment: &Comment) -> Vec<(usize, String)> {
+    let first_line = source[..comment.span.start].matches('\n').count() + 1;
+
+    let text = if comment.is_line_comment() {
+        &comment.text[2..]
+    } else {
+        &comment.text[2..comment.text.len() - 2]
+    };
+
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| {
+            let line = line.strip_prefix(' ').unwrap_or(line);
+            (first_line + index, line.to_string())
+        })
+        .collect()
+}
+
+// Find the ``` fenced blocks inside the comments of a program.
+// Consecutive line comments are read as one block of text
+pub fn examples(source: &str) -> Result<Vec<Example>, String> {
+    let comments = parse_comments(source).map_err(|err| format!("{}", err))?;
+    let lines = comments
+        .iter()
+        .flat_map(|comment| comment_lines(source, comment));
+
+    let mut examples = Vec::new();
+    let mut current: Option<Example> = None;
+
+    for (line, text) in lines {
+        if text.trim_start().starts_with("```") {
+            match current.take() {
+                Some(example) => examples.push(example),
+                None => {
+                    current = Some(Example {
+                        line: line + 1,
+                        code: String::new(),
+                    })
+                }
+            }
+        } else if let Some(example) = &mut current {
+            example.code.push_str(&text);
+            example.code.push('\n');
+        }
+    }
+
+    match current {
+        Some(example) => Err(format!(
+            "Code block starting on line {} is never closed",
+            example.line - 1
+        )),
+        None => Ok(examples),
+    }
+}
+
+// Type check an example, returning every problem with it
+pub fn check_example(example: &Example) -> Result<(), String> {
+    let ast = parse_expression(&example.code).map_err(|err| format!("{}", err))?;
+
+    match get_type_or_errors(ast) {
+        Ok(_) => Ok(()),
+        Err(errors) => {
+            let messages: Vec<_> = errors.iter().map(|err| format!("{}", err.error)).collect();
+            Err(messages.join("\n\n"))
+        }
+    }
+}
@@ -0,0 +1,147 @@
+// Indented s-expressions showing the structures the compiler works with, for `steiner dump`
+use crate::parser::{Ast, Statement};
+use crate::type_checker::typed_ast::{TypedAst, TypedNode};
+
+// A node with its head on the first line and each child indented on a line of its own
+fn node(head: String, children: Vec<String>) -> String {
+    if children.is_empty() {
+        return format!("({})", head);
+    }
+
+    let children: Vec<_> = children
+        .iter()
+        .map(|child| format!("\n  {}", child.replace('\n', "\n  ")))
+        .collect();
+
+    format!("({}{})", head, children.concat())
+}
+
+// Spans and comments would only get in the way, so those get skipped
+pub fn ast(expression: &Ast) -> String {
+    match expression {
+        Ast::Variable(name) => node(format!("var {}", name), vec![]),
+        Ast::FloatLiteral(value) => node(format!("float {}", value), vec![]),
+        Ast::StringLiteral(value) => node(format!("string {:?}", value), vec![]),
+        Ast::CharLiteral(value) => node(format!("char {:?}", value), vec![]),
+        Ast::CustomLiteral(text) => node(format!("custom #{}", text), vec![]),
+        Ast::If(condition, then, otherwise) => node(
+            "if".to_string(),
+            vec![ast(condition), ast(then), ast(otherwise)],
+        ),
+        Ast::Let(name, value, body) => node(format!("let {}", name), vec![ast(value), ast(body)]),
+        Ast::FunctionCall(function, argument) => {
+            node("call".to_string(), vec![ast(function), ast(argument)])
+        }
+        Ast::Lambda(argument, body) => node(format!("lambda {}", argument), vec![ast(body)]),
+        Ast::Annotation(expression, ty) => {
+            node(format!("annotation {}", ty), vec![ast(expression)])
+        }
+        Ast::Foreign(name, ty, body) => {
+            node(format!("foreign {} :: {}", name, ty), vec![ast(body)])
+        }
+        Ast::Operator(operator, left, right) => node(
+            format!("operator {}", operator.symbol()),
+            vec![ast(left), ast(right)],
+        ),
+        Ast::MultiWayIf(branches, otherwise) => {
+            let mut children: Vec<_> = branches
+                .iter()
+                .map(|(condition, value)| {
+                    node("branch".to_string(), vec![ast(condition), ast(value)])
+                })
+                .collect();
+            children.push(node("otherwise".to_string(), vec![ast(otherwise)]));
+
+            node("multi-way-if".to_string(), children)
+        }
+        Ast::Negation(inner) => node("negation".to_string(), vec![ast(inner)]),
+        Ast::Do(statements, result) => {
+            let mut children: Vec<_> = statements
+                .iter()
+                .map(|statement| match statement {
+                    Statement::Bind(name, action) => {
+                        node(format!("bind {}", name), vec![ast(action)])
+                    }
+                    Statement::Run(action) => ast(action),
+                })
+                .collect();
+            children.push(ast(result));
+
+            node("do".to_string(), children)
+        }
+        Ast::Error(text) => node(format!("error {:?}", text), vec![]),
+        Ast::Hole(_, Some(name)) => node(format!("hole ?{}", name), vec![]),
+        Ast::Hole(_, None) => node("hole _".to_string(), vec![]),
+        Ast::Located(_, expression) | Ast::Commented(_, expression) => ast(expression),
+    }
+}
+
+// Same as ast, with the type of every node after its head
+pub fn typed_ast(expression: &TypedAst) -> String {
+    let ty = &expression.ty;
+
+    match &expression.node {
+        TypedNode::Variable(name) => node(format!("var {} : {}", name, ty), vec![]),
+        TypedNode::Hole(Some(name)) => node(format!("hole ?{} : {}", name, ty), vec![]),
+        TypedNode::Hole(None) => node(format!("hole _ : {}", ty), vec![]),
+        TypedNode::Error(text) => node(format!("error {:?} : {}", text, ty), vec![]),
+        TypedNode::FloatLiteral(value) => node(format!("float {} : {}", value, ty), vec![]),
+        TypedNode::StringLiteral(value) => node(format!("string {:?} : {}", value, ty), vec![]),
+        TypedNode::CharLiteral(value) => node(format!("char {:?} : {}", value, ty), vec![]),
+        TypedNode::CustomLiteral(text) => node(format!("custom #{} : {}", text, ty), vec![]),
+        TypedNode::If(condition, then, otherwise) => node(
+            format!("if : {}", ty),
+            vec![typed_ast(condition), typed_ast(then), typed_ast(otherwise)],
+        ),
+        TypedNode::Let(name, value, body) => node(
+            format!("let {} : {}", name, ty),
+            vec![typed_ast(value), typed_ast(body)],
+        ),
+        TypedNode::FunctionCall(function, argument) => node(
+            format!("call : {}", ty),
+            vec![typed_ast(function), typed_ast(argument)],
+        ),
+        TypedNode::Lambda(argument, body) => node(
+            format!("lambda {} : {}", argument, ty),
+            vec![typed_ast(body)],
+        ),
+        TypedNode::Annotation(expression, annotation) => node(
+            format!("annotation {} : {}", annotation, ty),
+            vec![typed_ast(expression)],
+        ),
+        TypedNode::Foreign(name, declared, body) => node(
+            format!("foreign {} :: {} : {}", name, declared, ty),
+            vec![typed_ast(body)],
+        ),
+        TypedNode::Located(_, expression) => typed_ast(expression),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_expression;
+    use crate::type_checker::type_::get_typed_ast;
+
+    #[test]
+    fn children_get_indented_under_their_node() {
+        let expression = parse_expression("\\x -> add x 1").unwrap();
+
+        assert_eq!(
+            ast(&expression),
+            "(lambda x\n  (call\n    (call\n      (var add)\n      (var x))\n    (float 1)))"
+        );
+    }
+
+    // The types are the ones solving found, not the variables inference started with
+    #[test]
+    fn typed_nodes_show_their_types() {
+        let expression = parse_expression("let id = \\x -> x in id 1").unwrap();
+        let typed = get_typed_ast(expression).unwrap();
+
+        assert_eq!(
+            typed_ast(&typed),
+            "(let id : Number\n  (lambda x : a -> a\n    (var x : a))\n  (call : Number\n    (var id : Number -> Number)\n    (float 1 : Number)))"
+        );
+    }
+}
@@ -0,0 +1,231 @@
+// Every error code the compiler can report, with a longer explanation for `steiner explain`.
+// Codes are never reused, so they stay valid in bug reports and search results
+pub struct ErrorCode {
+    pub code: &'static str,
+    // Name of the TypeError variant using the code
+    pub name: &'static str,
+    pub explanation: &'static str,
+}
+
+pub const ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: "E0001",
+        name: "UnificationError",
+        explanation: "\
+Two types which have to be the same are different.
+
+Example:
+
+    add 1 \"two\"
+
+add takes two numbers, but the second argument is a string, so Number
+cannot be unified with String. Check the arguments passed to functions
+and the branches of if expressions, which must have the same type.",
+    },
+    ErrorCode {
+        code: "E0002",
+        name: "RecursiveType",
+        explanation: "\
+A type would have to contain itself, which would make it infinitely large.
+
+Example:
+
+    \\x -> x x
+
+x is applied to itself, so its type t has to be a function taking t,
+meaning t = t -> r. No finite type satisfies that.",
+    },
+    ErrorCode {
+        code: "E0003",
+        name: "MatchingError",
+        explanation: "\
+A type could not be matched against another one. Matching is like
+unification, except only variables on one side may be replaced.
+
+This usually shows up together with annotations which are more general
+than the expression they annotate.",
+    },
+    ErrorCode {
+        code: "E0004",
+        name: "NotInScope",
+        explanation: "\
+A variable is used which isn't defined anywhere.
+
+Example:
+
+    let double x = add x x in dobule 2
+
+dobule is a typo for double. When something with a similar name is in
+scope, it gets suggested after the error.",
+    },
+    ErrorCode {
+        code: "E0005",
+        name: "SubstitutionConflict",
+        explanation: "\
+The type checker found two different solutions for the same type variable
+while combining the results of solving constraints. This is an internal
+inconsistency, so please report the program which caused it.",
+    },
+    ErrorCode {
+        code: "E0006",
+        name: "DifferentLengths",
+        explanation: "\
+Two types were unified whose constructors take a different number of
+arguments. This is an internal inconsistency, since kinds should catch it
+earlier, so please report the program which caused it.",
+    },
+    ErrorCode {
+        code: "E0007",
+        name: "KindMismatch",
+        explanation: "\
+A type has a kind which can't be the one it needs, usually because it is
+applied to an argument even though it doesn't take any.
+
+Example:
+
+    \\x -> (x :: Number String)
+
+Number has kind *, so it cannot be applied to String. Types like List
+and Effect take one argument, as in List String.",
+    },
+    ErrorCode {
+        code: "E0008",
+        name: "EscapedSkolem",
+        explanation: "\
+A type variable from an annotation ended up in a type outside of that
+annotation. Variables in annotations stand for any type the caller wants,
+so they can't be tied to a type decided elsewhere.
+
+Example:
+
+    \\y -> (\\x -> y :: forall a. a -> a)
+
+The annotation promises to return any type a, but it returns y, whose
+type comes from outside of the annotation.",
+    },
+    ErrorCode {
+        code: "E0009",
+        name: "UnknownLiteral",
+        explanation: "\
+None of the registered literal handlers knows the type of a #... literal.
+
+Example:
+
+    #rgb(255 0 0)
+
+Custom literals only have types when the program embedding steiner
+registers a handler for them with TypeContextBuilder::with_literal.",
+    },
+    ErrorCode {
+        code: "E0010",
+        name: "TypeTooDeep",
+        explanation: "\
+A type variable would have to stand for a type nested more than 2000
+levels deep. Types this deep can't be worked with without running out of
+stack, so type checking stops there.
+
+Example:
+
+    let a = \\x -> cons x nil in
+    let b = \\x -> a (a (a (a (a (a (a (a x))))))) in
+    let c = \\x -> b (b (b (b (b (b (b (b x))))))) in
+    let d = \\x -> c (c (c (c (c (c (c (c x))))))) in
+    d (d (d (d (d 1))))
+
+Every let nests lists 8 times deeper than the one before, so the last d
+would have to take a list of lists 2048 levels deep.",
+    },
+    ErrorCode {
+        code: "E0011",
+        name: "NotEquatable",
+        explanation: "\
+eq or neq was used on values containing functions. Two functions can't
+be told apart without running them on every possible input, so they
+can't be compared, not even when they sit inside a list.
+
+Example:
+
+    eq (cons add nil) nil
+
+The lists being compared hold functions, so they can't be compared
+either. Compare the results of calling the functions instead.
+
+The same goes for lets comparing their arguments, which can only be
+called with values that can be compared, and for type variables from
+annotations, which promise to work for functions too:
+
+    let same = \\x y -> x == y in same add add
+    (\\x y -> x == y) :: forall a. a -> a -> Boolean",
+    },
+];
+
+// Look up a code such as E0001. The leading E is optional and case doesn't matter
+pub fn lookup(code: &str) -> Option<&'static ErrorCode> {
+    let code = code.to_uppercase();
+    let code = if code.starts_with('E') {
+        code
+    } else {
+        format!("E{}", code)
+    };
+
+    ERROR_CODES.iter().find(|entry| entry.code == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::check_source;
+    use crate::messages::Locale;
+    use crate::parser::with_stack;
+    use crate::type_checker::type_::TypeContext;
+
+    // The indented lines following "Example:", as a single program
+    fn example(entry: &ErrorCode) -> Option<String> {
+        let (_, rest) = entry.explanation.split_once("Example:\n\n")?;
+        let lines: Vec<_> = rest
+            .lines()
+            .take_while(|line| line.starts_with("    "))
+            .map(|line| &line[4..])
+            .collect();
+
+        Some(lines.join("\n"))
+    }
+
+    #[test]
+    fn codes_are_listed_in_order_once() {
+        for (index, entry) in ERROR_CODES.iter().enumerate() {
+            assert_eq!(entry.code, format!("E{:04}", index + 1));
+        }
+    }
+
+    #[test]
+    fn codes_get_looked_up_loosely() {
+        assert_eq!(lookup("E0004").unwrap().name, "NotInScope");
+        assert_eq!(lookup("e0004").unwrap().name, "NotInScope");
+        assert_eq!(lookup("0004").unwrap().name, "NotInScope");
+        assert!(lookup("E9999").is_none());
+    }
+
+    // Type checking the example of a code reports that code. Some of them build really
+    // deep types, so this gets a stack of its own
+    #[test]
+    fn examples_report_their_code() {
+        with_stack(|| {
+            for entry in ERROR_CODES {
+                if let Some(source) = example(entry) {
+                    let (_, diagnostics) =
+                        check_source(&source, TypeContext::new(), Locale::English);
+
+                    assert!(
+                        diagnostics
+                            .iter()
+                            .any(|diagnostic| diagnostic.code == Some(entry.code)),
+                        "{} gave {:?}",
+                        entry.code,
+                        diagnostics
+                    );
+                }
+            }
+        });
+    }
+}
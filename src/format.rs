@@ -0,0 +1,613 @@
+use crate::parser::{Ast, Operator, Statement};
+
+// How many spaces nested lines get
+const INDENT: usize = 4;
+
+// Width the formatter tries to stay under
+pub const DEFAULT_WIDTH: usize = 80;
+
+// A document which knows where it's allowed to break lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Doc {
+    Text(String),
+    // A space when the surrounding group fits on one line, a newline otherwise
+    Line,
+    // Always a newline, forcing every group around it to break
+    HardLine,
+    Nest(usize, Box<Doc>),
+    // Either everything inside is laid out flat or every line in it breaks
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+impl Doc {
+    pub fn text(text: &str) -> Doc {
+        Doc::Text(text.to_string())
+    }
+
+    pub fn nest(self: Doc, indent: usize) -> Doc {
+        Doc::Nest(indent, Box::new(self))
+    }
+
+    pub fn group(self: Doc) -> Doc {
+        Doc::Group(Box::new(self))
+    }
+
+    // The character the document starts with, if it prints anything
+    fn first_character(self: &Doc) -> Option<char> {
+        match self {
+            Doc::Text(text) => text.chars().next(),
+            Doc::Line | Doc::HardLine => Some(' '),
+            Doc::Nest(_, doc) | Doc::Group(doc) => doc.first_character(),
+            Doc::Concat(docs) => docs.iter().find_map(Doc::first_character),
+        }
+    }
+
+    // Width of the document if it's all put on one line
+    fn flat_width(self: &Doc) -> usize {
+        match self {
+            Doc::Text(text) => text.chars().count(),
+            Doc::Line => 1,
+            Doc::HardLine => usize::MAX / 2,
+            Doc::Nest(_, doc) | Doc::Group(doc) => doc.flat_width(),
+            Doc::Concat(docs) => docs
+                .iter()
+                .map(Doc::flat_width)
+                .fold(0, usize::saturating_add),
+        }
+    }
+
+    pub fn render(self: &Doc, width: usize) -> String {
+        let mut output = String::new();
+        let mut column = 0;
+        // Nothing but indentation has been written since the last newline
+        let mut line_start = true;
+        // (indentation, whether lines should break, document)
+        let mut stack = vec![(0, true, self)];
+
+        while let Some((indent, breaking, doc)) = stack.pop() {
+            match doc {
+                Doc::Text(text) => {
+                    output.push_str(text);
+                    // Multi-line strings can put newlines inside text
+                    column = match text.rfind('\n') {
+                        Some(newline) => text[newline + 1..].chars().count(),
+                        None => column + text.chars().count(),
+                    };
+                    line_start = false;
+                }
+                Doc::Line | Doc::HardLine if breaking || *doc == Doc::HardLine => {
+                    // Don't leave empty lines behind when breaks follow each other
+                    if line_start {
+                        output.truncate(output.trim_end_matches(' ').len());
+                    } else {
+                        output.push('\n');
+                    }
+
+                    output.push_str(&" ".repeat(indent));
+                    column = indent;
+                    line_start = true;
+                }
+                Doc::Line | Doc::HardLine => {
+                    output.push(' ');
+                    column += 1;
+                }
+                Doc::Nest(extra, doc) => stack.push((indent + extra, breaking, doc)),
+                Doc::Group(doc) => {
+                    let fits = column + doc.flat_width() <= width;
+                    stack.push((indent, breaking && !fits, doc))
+                }
+                Doc::Concat(docs) => {
+                    for doc in docs.iter().rev() {
+                        stack.push((indent, breaking, doc))
+                    }
+                }
+            }
+        }
+
+        output.trim_end().to_string()
+    }
+}
+
+// Where an expression is being printed, which decides if it needs parenthesis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    Anywhere,
+    // Left of a ::
+    Annotated,
+    Function,
+    Argument,
+    // Next to an operator, which only leaves room for operators binding at least this tightly
+    Operand(usize),
+}
+
+// How tightly an operator binds, same as in the grammar. Calls bind tighter than all of them
+fn precedence(operator: Operator) -> usize {
+    match operator {
+        Operator::Pipe => 1,
+        Operator::Equal | Operator::NotEqual => 2,
+        Operator::ComposeForward | Operator::ComposeBackward => 3,
+    }
+}
+
+// Comparisons are the only operators which can't be chained
+fn chains(operator: Operator) -> bool {
+    !matches!(operator, Operator::Equal | Operator::NotEqual)
+}
+
+fn strip_location(ast: &Ast) -> &Ast {
+    match ast {
+        Ast::Located(_, inner) => strip_location(inner),
+        other => other,
+    }
+}
+
+// Collect the parameters of nested lambdas
+fn lambda_parameters(ast: &Ast) -> (Vec<&String>, &Ast) {
+    match strip_location(ast) {
+        Ast::Lambda(parameter, body) => {
+            let (mut parameters, body) = lambda_parameters(body);
+            parameters.insert(0, parameter);
+            (parameters, body)
+        }
+        other => (vec![], other),
+    }
+}
+
+// Collect the arguments of nested calls
+fn call_arguments(ast: &Ast) -> (&Ast, Vec<&Ast>) {
+    match strip_location(ast) {
+        Ast::FunctionCall(function, argument) => {
+            let (function, mut arguments) = call_arguments(function);
+            arguments.push(argument);
+            (function, arguments)
+        }
+        other => (other, vec![]),
+    }
+}
+
+// Collect the operands of operators chained to the left, like a |> b |> c
+fn operator_chain(ast: &Ast) -> (&Ast, Vec<(Operator, &Ast)>) {
+    match strip_location(ast) {
+        Ast::Operator(operator, left, right) => {
+            let (first, mut rest) = match strip_location(left) {
+                Ast::Operator(inner, _, _)
+                    if chains(*operator) && precedence(*inner) == precedence(*operator) =>
+                {
+                    operator_chain(left)
+                }
+                _ => (&**left, vec![]),
+            };
+            rest.push((*operator, right));
+            (first, rest)
+        }
+        other => (other, vec![]),
+    }
+}
+
+// Write a character the way it has to appear between the given quotes
+fn escape_char(character: char, quote: char) -> String {
+    match character {
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        '\0' => "\\0".to_string(),
+        '\\' => "\\\\".to_string(),
+        character if character == quote => format!("\\{}", character),
+        character if character.is_control() => format!("\\u{{{:x}}}", character as u32),
+        character => character.to_string(),
+    }
+}
+
+// Strings with newlines in them get written as triple quoted strings
+fn escape_string(string: &str) -> String {
+    if string.contains('\n') {
+        let escaped: String = string
+            .chars()
+            .map(|character| match character {
+                '\n' => "\n".to_string(),
+                character => escape_char(character, '"'),
+            })
+            .collect();
+
+        format!("\"\"\"{}\"\"\"", escaped)
+    } else {
+        let escaped: String = string
+            .chars()
+            .map(|character| escape_char(character, '"'))
+            .collect();
+
+        format!("\"{}\"", escaped)
+    }
+}
+
+fn parenthesize(doc: Doc) -> Doc {
+    Doc::Concat(vec![Doc::text("("), doc.nest(1), Doc::text(")")])
+}
+
+// Turn an expression into a document
+pub fn to_doc(ast: &Ast) -> Doc {
+    to_doc_at(ast, Position::Anywhere)
+}
+
+fn to_doc_at(ast: &Ast, position: Position) -> Doc {
+    match ast {
+        Ast::Located(_, inner) => to_doc_at(inner, position),
+        Ast::Commented(trivia, inner) => {
+            let mut docs = Vec::new();
+
+            for comment in &trivia.leading {
+                docs.push(Doc::text(&comment.text));
+                docs.push(if comment.is_line_comment() {
+                    Doc::HardLine
+                } else {
+                    Doc::text(" ")
+                });
+            }
+
+            docs.push(to_doc_at(inner, position));
+
+            for comment in &trivia.trailing {
+                docs.push(Doc::Text(format!(" {}", comment.text)));
+
+                if comment.is_line_comment() {
+                    docs.push(Doc::HardLine);
+                }
+            }
+
+            Doc::Concat(docs)
+        }
+        Ast::Variable(name) => Doc::text(name),
+        Ast::Hole(_, Some(name)) => Doc::Text(format!("?{}", name)),
+        Ast::Hole(_, None) => Doc::text("_"),
+        Ast::Error(text) => Doc::text(text),
+        Ast::FloatLiteral(value) => Doc::Text(format!("{}", value)),
+        Ast::StringLiteral(value) => Doc::Text(escape_string(value)),
+        Ast::CharLiteral(value) => Doc::Text(format!("'{}'", escape_char(*value, '\''))),
+        Ast::CustomLiteral(text) => Doc::Text(format!("#{}", text)),
+        Ast::Annotation(annotated, annotation) => {
+            let doc = Doc::Concat(vec![
+                to_doc_at(annotated, Position::Annotated),
+                Doc::Concat(vec![Doc::Line, Doc::Text(format!(":: {}", annotation))]).nest(INDENT),
+            ])
+            .group();
+
+            match position {
+                Position::Function | Position::Argument | Position::Operand(_) => parenthesize(doc),
+                _ => doc,
+            }
+        }
+        Ast::Operator(operator, _, _) => {
+            let precedence = precedence(*operator);
+            let (first, rest) = operator_chain(ast);
+            let first_precedence = if chains(*operator) {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let mut docs = vec![to_doc_at(first, Position::Operand(first_precedence))];
+
+            for (operator, operand) in rest {
+                docs.push(Doc::Line);
+                docs.push(Doc::Text(format!("{} ", operator.symbol())));
+                docs.push(to_doc_at(operand, Position::Operand(precedence + 1)));
+            }
+
+            let doc = Doc::Concat(docs).nest(INDENT).group();
+
+            match position {
+                Position::Function | Position::Argument => parenthesize(doc),
+                Position::Operand(required) if precedence < required => parenthesize(doc),
+                _ => doc,
+            }
+        }
+        Ast::Negation(inner) => {
+            // Anywhere but in an argument, the negation takes in the whole call after it
+            let operand = if position == Position::Argument {
+                to_doc_at(inner, Position::Argument)
+            } else {
+                to_doc_at(inner, Position::Function)
+            };
+            // -1 is a number and --x a comment, so operands starting like that need parenthesis
+            let operand = match operand.first_character() {
+                Some(character) if character == '-' || character.is_ascii_digit() => {
+                    parenthesize(operand)
+                }
+                _ => operand,
+            };
+            let doc = Doc::Concat(vec![Doc::text("-"), operand]);
+
+            match position {
+                Position::Function => parenthesize(doc),
+                _ => doc,
+            }
+        }
+        Ast::FunctionCall(_, _) => {
+            let (function, arguments) = call_arguments(ast);
+            let mut docs = vec![to_doc_at(function, Position::Function)];
+
+            for argument in arguments {
+                docs.push(Doc::Line);
+                docs.push(to_doc_at(argument, Position::Argument));
+            }
+
+            let doc = Doc::Concat(docs).nest(INDENT).group();
+
+            match position {
+                Position::Argument => parenthesize(doc),
+                _ => doc,
+            }
+        }
+        Ast::Lambda(_, _) => {
+            let (parameters, body) = lambda_parameters(ast);
+            let head = parameters
+                .iter()
+                .map(|parameter| parameter.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            let doc = Doc::Concat(vec![
+                Doc::Text(format!("\\{} ->", head)),
+                Doc::Concat(vec![Doc::Line, to_doc(body)]).nest(INDENT),
+            ])
+            .group();
+
+            wrap_open_ended(doc, position)
+        }
+        Ast::If(condition, left, right) => {
+            let doc = Doc::Concat(vec![
+                Doc::text("if "),
+                to_doc(condition).nest(3),
+                Doc::Line,
+                Doc::text("then "),
+                to_doc(left).nest(INDENT),
+                Doc::Line,
+                Doc::text("else "),
+                to_doc(right).nest(INDENT),
+            ])
+            .group();
+
+            wrap_open_ended(doc, position)
+        }
+        Ast::Let(name, value, body) => {
+            let (parameters, value) = lambda_parameters(value);
+            let mut head = vec![name.as_str()];
+            head.extend(parameters.iter().map(|parameter| parameter.as_str()));
+
+            // The body only goes on a line of its own when it doesn't fit after the in, so
+            // comments breaking up the value don't push it down as well
+            let doc = Doc::Concat(vec![
+                Doc::Concat(vec![
+                    Doc::Text(format!("let {} =", head.join(" "))),
+                    Doc::Concat(vec![Doc::Line, to_doc(value)]).nest(INDENT),
+                    Doc::Line,
+                    Doc::text("in"),
+                ])
+                .group(),
+                Doc::Concat(vec![Doc::Line, to_doc(body)]).group(),
+            ]);
+
+            wrap_open_ended(doc, position)
+        }
+        Ast::Do(statements, result) => {
+            let mut docs = Vec::new();
+
+            for statement in statements {
+                docs.push(Doc::Line);
+                docs.push(match statement {
+                    Statement::Bind(name, action) => {
+                        Doc::Concat(vec![Doc::Text(format!("{} <- ", name)), to_doc(action)])
+                    }
+                    Statement::Run(action) => to_doc(action),
+                });
+                docs.push(Doc::text(";"));
+            }
+
+            docs.push(Doc::Line);
+            docs.push(to_doc(result));
+
+            Doc::Concat(vec![
+                Doc::text("do {"),
+                Doc::Concat(docs).nest(INDENT),
+                Doc::Line,
+                Doc::text("}"),
+            ])
+            .group()
+        }
+        Ast::MultiWayIf(branches, otherwise) => {
+            let mut docs = vec![Doc::text("if")];
+
+            for (condition, value) in branches {
+                // The type of an annotated condition would take in the arrow after it
+                let condition = to_doc_at(condition, Position::Operand(0));
+
+                docs.push(Doc::Line);
+                docs.push(Doc::Concat(vec![
+                    Doc::text("| "),
+                    condition.nest(2),
+                    Doc::text(" ->"),
+                    Doc::Concat(vec![Doc::Line, to_doc(value)])
+                        .nest(INDENT)
+                        .group(),
+                ]));
+            }
+
+            docs.push(Doc::Line);
+            docs.push(Doc::Concat(vec![
+                Doc::text("| otherwise ->"),
+                Doc::Concat(vec![Doc::Line, to_doc(otherwise)])
+                    .nest(INDENT)
+                    .group(),
+            ]));
+
+            wrap_open_ended(Doc::Concat(docs).nest(INDENT).group(), position)
+        }
+        Ast::Foreign(name, ty, body) => {
+            let doc = Doc::Concat(vec![
+                Doc::Text(format!("foreign import {} :: {} in", name, ty)),
+                Doc::Line,
+                to_doc(body),
+            ])
+            .group();
+
+            wrap_open_ended(doc, position)
+        }
+    }
+}
+
+// Lambdas, ifs, lets and foreign imports swallow everything to their right
+fn wrap_open_ended(doc: Doc, position: Position) -> Doc {
+    match position {
+        Position::Anywhere => doc,
+        _ => parenthesize(doc),
+    }
+}
+
+// Pretty print an expression back to source
+pub fn format_expression(ast: &Ast, width: usize) -> String {
+    to_doc(ast).render(width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_expression;
+
+    fn formatted(source: &str) -> String {
+        format_expression(&parse_expression(source).unwrap(), DEFAULT_WIDTH)
+    }
+
+    // Let-bound lambdas go back to the function declaration sugar, and chains of lambdas
+    // get merged into one
+    #[test]
+    fn lambdas_get_printed_canonically() {
+        assert_eq!(
+            formatted("let f = \\x -> \\y -> x in f"),
+            "let f x y = x in f"
+        );
+        assert_eq!(formatted("\\x -> \\y -> x"), "\\x y -> x");
+    }
+
+    #[test]
+    fn parentheses_only_go_where_they_are_needed() {
+        assert_eq!(formatted("((f) (x))"), "f x");
+        assert_eq!(formatted("(f x) y"), "f x y");
+        assert_eq!(formatted("f (g x)"), "f (g x)");
+        assert_eq!(formatted("(\\x -> x) 1"), "(\\x -> x) 1");
+    }
+
+    // Values which don't fit next to their name go on a line of their own
+    #[test]
+    fn long_let_values_get_nested() {
+        let value = "cons (add element 1) (cons element (cons element (cons element nil)))";
+
+        assert_eq!(
+            formatted(&format!("let value = {} in value", value)),
+            format!("let value =\n    {}\nin value", value)
+        );
+    }
+
+    // Characters which can't be written as they are get escaped on the way back out
+    #[test]
+    fn characters_get_escaped() {
+        assert_eq!(formatted("'\\n'"), "'\\n'");
+        assert_eq!(formatted("'\\''"), "'\\''");
+        assert_eq!(formatted("'\"'"), "'\"'");
+    }
+
+    // Strings with newlines in them keep their lines by getting triple quoted
+    #[test]
+    fn strings_get_escaped() {
+        assert_eq!(formatted("\"a\\tb\\\"c\""), "\"a\\tb\\\"c\"");
+        assert_eq!(formatted("\"a\\nb\""), "\"\"\"a\nb\"\"\"");
+    }
+
+    #[test]
+    fn pipelines_stay_pipelines() {
+        assert_eq!(formatted("xs |> map f |> length"), "xs |> map f |> length");
+        assert_eq!(formatted("x |> (f |> g)"), "x |> (f |> g)");
+        assert_eq!(formatted("(\\x -> x) |> f"), "(\\x -> x) |> f");
+        assert_eq!(formatted("f (x |> g)"), "f (x |> g)");
+    }
+
+    #[test]
+    fn compositions_stay_compositions() {
+        assert_eq!(formatted("f >> g"), "f >> g");
+        assert_eq!(formatted("f >> g << h"), "f >> g << h");
+        assert_eq!(formatted("f >> (g << h)"), "f >> (g << h)");
+        assert_eq!(formatted("x |> f >> g"), "x |> f >> g");
+        assert_eq!(formatted("(x |> f) >> g"), "(x |> f) >> g");
+    }
+
+    #[test]
+    fn comparisons_stay_comparisons() {
+        assert_eq!(formatted("a == b"), "a == b");
+        assert_eq!(formatted("a /= b"), "a /= b");
+        assert_eq!(formatted("(a == b) == c"), "(a == b) == c");
+        assert_eq!(formatted("f >> g == h |> k"), "f >> g == h |> k");
+        assert_eq!(formatted("(a |> f) == b"), "(a |> f) == b");
+    }
+
+    #[test]
+    fn negations_stay_negations() {
+        assert_eq!(formatted("-x"), "-x");
+        assert_eq!(formatted("-add 1 2"), "-add 1 2");
+        assert_eq!(formatted("f -x"), "f -x");
+        assert_eq!(formatted("f -(g x)"), "f -(g x)");
+        assert_eq!(formatted("(-f) x"), "(-f) x");
+        assert_eq!(formatted("-(-x)"), "-(-x)");
+        assert_eq!(formatted("-(1)"), "-(1)");
+        assert_eq!(formatted("-1"), "-1");
+        assert_eq!(formatted("-(1 x)"), "-(1 x)");
+        assert_eq!(formatted("-(-1 x)"), "-(-1 x)");
+        assert_eq!(formatted("-f x |> g"), "-f x |> g");
+    }
+
+    #[test]
+    fn multi_way_ifs_stay_multi_way() {
+        let source = "if | c -> a | d -> b | otherwise -> e";
+
+        assert_eq!(formatted(source), source);
+        assert_eq!(
+            formatted("f (if | c -> a | otherwise -> b)"),
+            "f (if | c -> a | otherwise -> b)"
+        );
+        assert_eq!(
+            formatted("if | (c :: Boolean) -> a | otherwise -> b"),
+            "if | (c :: Boolean) -> a | otherwise -> b"
+        );
+    }
+
+    // Every branch gets a line of its own when they don't fit on one
+    #[test]
+    fn long_multi_way_ifs_break_between_branches() {
+        let value = "cons (add element 1) (cons element nil)";
+        let source = format!("if | c -> {} | otherwise -> {}", value, value);
+
+        assert_eq!(
+            formatted(&source),
+            format!("if\n    | c -> {}\n    | otherwise -> {}", value, value)
+        );
+    }
+
+    #[test]
+    fn comments_in_let_values_keep_the_body_after_in() {
+        assert_eq!(
+            formatted("let x = 1 -- c\nin x"),
+            "let x =\n    1 -- c\nin x"
+        );
+        assert_eq!(formatted("let x = 1 in x"), "let x = 1 in x");
+    }
+
+    // Stages which don't fit go on lines of their own
+    #[test]
+    fn long_pipelines_break_before_every_stage() {
+        let stage = "map (\\element -> add element 1)";
+        let source = format!("xs |> {} |> {} |> length", stage, stage);
+
+        assert_eq!(
+            formatted(&source),
+            format!("xs\n    |> {}\n    |> {}\n    |> length", stage, stage)
+        );
+    }
+}
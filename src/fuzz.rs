@@ -0,0 +1,69 @@
+// Entry points for running the parser and type checker under a fuzzer like cargo-fuzz.
+// None of them may panic, whatever the input
+use crate::diagnostic::check_source;
+use crate::format::{format_expression, DEFAULT_WIDTH};
+use crate::messages::Locale;
+use crate::parser::{parse_expression, parse_expression_recovering};
+use crate::type_checker::type_::TypeContext;
+use std::str;
+
+// Fuzzers call these on whatever stack they have, which isn't enough for programs nested
+// close to MAX_NESTING. Inputs this short can only nest about half as deep
+const MAX_INPUT: usize = 512;
+
+// Parse arbitrary bytes, with and without recovering from errors, and format what parsed
+pub fn fuzz_parse(data: &[u8]) {
+    let source = match str::from_utf8(data) {
+        Ok(source) if source.len() <= MAX_INPUT => source,
+        _ => return,
+    };
+
+    parse_expression_recovering(source);
+
+    if let Ok(ast) = parse_expression(source) {
+        format_expression(&ast, DEFAULT_WIDTH);
+    }
+}
+
+// Type check a program the way `steiner check` does, strict kinds included
+pub fn fuzz_check(source: &str) {
+    if source.len() > MAX_INPUT {
+        return;
+    }
+
+    check_source(source, TypeContext::new(), Locale::default());
+    check_source(
+        source,
+        TypeContext::new().with_strict_kinds(),
+        Locale::default(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::with_stack;
+
+    // Inputs which used to take forever or crash, filling up MAX_INPUT with nesting
+    fn regressions() -> Vec<String> {
+        vec![
+            // Every let got parsed twice per level, so 39 of them never finished
+            format!("{}x", "let x = 1 in ".repeat(39)),
+            format!("{}1{}", "(".repeat(255), ")".repeat(255)),
+            format!("{}1", "\\x -> ".repeat(72)),
+            format!("1{}", " |> negate".repeat(50)),
+        ]
+    }
+
+    #[test]
+    fn deep_nesting_regressions() {
+        with_stack(|| {
+            for source in regressions() {
+                assert!(source.len() <= MAX_INPUT);
+
+                fuzz_parse(source.as_bytes());
+                fuzz_check(&source);
+            }
+        });
+    }
+}
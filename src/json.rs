@@ -0,0 +1,440 @@
+// Json for the structures tools might want to look at, like parse trees and inferred types.
+// Written by hand since we don't depend on serde
+use crate::diagnostic::Diagnostic;
+use crate::parser::{Ast, Comment, Span, Statement, Trivia};
+use crate::type_checker::trace::{StepKind, TraceStep};
+use crate::type_checker::type_::{LocatedError, Substitution, Type, TypeError, VarName};
+use crate::type_checker::typed_ast::{TypedAst, TypedNode};
+
+pub trait ToJson {
+    fn to_json(self: &Self) -> String;
+}
+
+// Quote a string, escaping everything json doesn't allow inside one
+pub fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+
+    for character in text.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            character if character.is_control() => {
+                escaped.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            character => escaped.push(character),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+// Build an object out of keys and already encoded values
+fn object(fields: Vec<(&str, String)>) -> String {
+    let fields: Vec<_> = fields
+        .into_iter()
+        .map(|(key, value)| format!("{}:{}", json_string(key), value))
+        .collect();
+
+    format!("{{{}}}", fields.join(","))
+}
+
+// A json array of anything which can be turned into json
+pub fn array<T: ToJson>(items: &[T]) -> String {
+    let items: Vec<_> = items.iter().map(ToJson::to_json).collect();
+    format!("[{}]", items.join(","))
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(self: &Self) -> String {
+        match self {
+            Some(value) => value.to_json(),
+            None => "null".to_string(),
+        }
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for Box<T> {
+    fn to_json(self: &Self) -> String {
+        (**self).to_json()
+    }
+}
+
+impl ToJson for String {
+    fn to_json(self: &Self) -> String {
+        json_string(self)
+    }
+}
+
+impl ToJson for Span {
+    fn to_json(self: &Self) -> String {
+        format!("{{\"start\":{},\"end\":{}}}", self.start, self.end)
+    }
+}
+
+impl ToJson for VarName {
+    fn to_json(self: &Self) -> String {
+        object(vec![
+            ("name", json_string(&self.name)),
+            ("kind", self.kind.to_json()),
+        ])
+    }
+}
+
+// Kinds are types as well, so every type carries its kind all the way down to *
+impl ToJson for Type {
+    fn to_json(self: &Self) -> String {
+        match self {
+            Type::Constructor(name) => object(vec![("constructor", name.to_json())]),
+            Type::Variable(name) => object(vec![("variable", name.to_json())]),
+            Type::TApply(function, input) => object(vec![(
+                "apply",
+                format!("[{},{}]", function.to_json(), input.to_json()),
+            )]),
+            Type::Scheme { variables, ty } => {
+                object(vec![("forall", array(variables)), ("type", ty.to_json())])
+            }
+            // Only ever shows up as the kind of kinds
+            Type::NoKind => "null".to_string(),
+            Type::ArrowKind => json_string("->"),
+            Type::Error => json_string("error"),
+        }
+    }
+}
+
+impl ToJson for Trivia {
+    fn to_json(self: &Self) -> String {
+        let texts = |comments: &[_]| {
+            let texts: Vec<_> = comments
+                .iter()
+                .map(|comment: &Comment| json_string(&comment.text))
+                .collect();
+            format!("[{}]", texts.join(","))
+        };
+
+        object(vec![
+            ("leading", texts(&self.leading)),
+            ("trailing", texts(&self.trailing)),
+        ])
+    }
+}
+
+impl ToJson for Statement {
+    fn to_json(self: &Self) -> String {
+        match self {
+            Statement::Bind(name, action) => object(vec![
+                ("bind", json_string(name)),
+                ("action", action.to_json()),
+            ]),
+            Statement::Run(action) => object(vec![("run", action.to_json())]),
+        }
+    }
+}
+
+// Every node is an object with a "node" field telling which kind of expression it is
+impl ToJson for Ast {
+    fn to_json(self: &Self) -> String {
+        let node = |name: &str, mut fields: Vec<(&str, String)>| {
+            fields.insert(0, ("node", json_string(name)));
+            object(fields)
+        };
+
+        match self {
+            Ast::Variable(name) => node("variable", vec![("name", json_string(name))]),
+            Ast::FloatLiteral(value) => node("float", vec![("value", value.to_string())]),
+            Ast::StringLiteral(value) => node("string", vec![("value", json_string(value))]),
+            Ast::CharLiteral(value) => {
+                node("char", vec![("value", json_string(&value.to_string()))])
+            }
+            Ast::CustomLiteral(text) => node("custom", vec![("text", json_string(text))]),
+            Ast::If(condition, then, otherwise) => node(
+                "if",
+                vec![
+                    ("condition", condition.to_json()),
+                    ("then", then.to_json()),
+                    ("else", otherwise.to_json()),
+                ],
+            ),
+            Ast::Let(name, value, body) => node(
+                "let",
+                vec![
+                    ("name", json_string(name)),
+                    ("value", value.to_json()),
+                    ("body", body.to_json()),
+                ],
+            ),
+            Ast::FunctionCall(function, argument) => node(
+                "call",
+                vec![
+                    ("function", function.to_json()),
+                    ("argument", argument.to_json()),
+                ],
+            ),
+            Ast::Lambda(argument, body) => node(
+                "lambda",
+                vec![
+                    ("argument", json_string(argument)),
+                    ("body", body.to_json()),
+                ],
+            ),
+            Ast::Annotation(expression, ty) => node(
+                "annotation",
+                vec![("expression", expression.to_json()), ("type", ty.to_json())],
+            ),
+            Ast::Foreign(name, ty, body) => node(
+                "foreign",
+                vec![
+                    ("name", json_string(name)),
+                    ("type", ty.to_json()),
+                    ("body", body.to_json()),
+                ],
+            ),
+            Ast::Operator(operator, left, right) => node(
+                "operator",
+                vec![
+                    ("operator", json_string(operator.symbol())),
+                    ("left", left.to_json()),
+                    ("right", right.to_json()),
+                ],
+            ),
+            Ast::MultiWayIf(branches, otherwise) => {
+                let branches: Vec<_> = branches
+                    .iter()
+                    .map(|(condition, value)| {
+                        object(vec![
+                            ("condition", condition.to_json()),
+                            ("value", value.to_json()),
+                        ])
+                    })
+                    .collect();
+
+                node(
+                    "multi-way-if",
+                    vec![
+                        ("branches", format!("[{}]", branches.join(","))),
+                        ("otherwise", otherwise.to_json()),
+                    ],
+                )
+            }
+            Ast::Negation(inner) => node("negation", vec![("expression", inner.to_json())]),
+            Ast::Do(statements, result) => node(
+                "do",
+                vec![
+                    ("statements", array(statements)),
+                    ("result", result.to_json()),
+                ],
+            ),
+            Ast::Error(text) => node("error", vec![("text", json_string(text))]),
+            Ast::Hole(offset, name) => node(
+                "hole",
+                vec![("offset", offset.to_string()), ("name", name.to_json())],
+            ),
+            Ast::Located(span, expression) => node(
+                "located",
+                vec![
+                    ("span", span.to_json()),
+                    ("expression", expression.to_json()),
+                ],
+            ),
+            Ast::Commented(trivia, expression) => node(
+                "commented",
+                vec![
+                    ("comments", trivia.to_json()),
+                    ("expression", expression.to_json()),
+                ],
+            ),
+        }
+    }
+}
+
+// Same shape as the untyped tree, with a "type" field on every node
+impl ToJson for TypedAst {
+    fn to_json(self: &Self) -> String {
+        let (name, mut fields) = match &self.node {
+            TypedNode::Variable(name) => ("variable", vec![("name", json_string(name))]),
+            TypedNode::Hole(name) => ("hole", vec![("name", name.to_json())]),
+            TypedNode::Error(text) => ("error", vec![("text", json_string(text))]),
+            TypedNode::FloatLiteral(value) => ("float", vec![("value", value.to_string())]),
+            TypedNode::StringLiteral(value) => ("string", vec![("value", json_string(value))]),
+            TypedNode::CharLiteral(value) => {
+                ("char", vec![("value", json_string(&value.to_string()))])
+            }
+            TypedNode::CustomLiteral(text) => ("custom", vec![("text", json_string(text))]),
+            TypedNode::If(condition, then, otherwise) => (
+                "if",
+                vec![
+                    ("condition", condition.to_json()),
+                    ("then", then.to_json()),
+                    ("else", otherwise.to_json()),
+                ],
+            ),
+            TypedNode::Let(name, value, body) => (
+                "let",
+                vec![
+                    ("name", json_string(name)),
+                    ("value", value.to_json()),
+                    ("body", body.to_json()),
+                ],
+            ),
+            TypedNode::FunctionCall(function, argument) => (
+                "call",
+                vec![
+                    ("function", function.to_json()),
+                    ("argument", argument.to_json()),
+                ],
+            ),
+            TypedNode::Lambda(argument, body) => (
+                "lambda",
+                vec![
+                    ("argument", json_string(argument)),
+                    ("body", body.to_json()),
+                ],
+            ),
+            TypedNode::Annotation(expression, annotation) => (
+                "annotation",
+                vec![
+                    ("expression", expression.to_json()),
+                    ("annotation", annotation.to_json()),
+                ],
+            ),
+            TypedNode::Foreign(name, declared, body) => (
+                "foreign",
+                vec![
+                    ("name", json_string(name)),
+                    ("declared", declared.to_json()),
+                    ("body", body.to_json()),
+                ],
+            ),
+            TypedNode::Located(span, expression) => (
+                "located",
+                vec![
+                    ("span", span.to_json()),
+                    ("expression", expression.to_json()),
+                ],
+            ),
+        };
+
+        fields.insert(0, ("node", json_string(name)));
+        fields.push(("type", self.ty.to_json()));
+        object(fields)
+    }
+}
+
+// The code and english message, together with the types involved
+impl ToJson for TypeError {
+    fn to_json(self: &Self) -> String {
+        let error = self.normalized();
+        let types = match &error {
+            TypeError::UnificationError(left, right)
+            | TypeError::MatchingError(left, right)
+            | TypeError::SubstitutionConflict(_, left, right) => vec![left.clone(), right.clone()],
+            TypeError::RecursiveType(_, ty)
+            | TypeError::EscapedSkolem(ty)
+            | TypeError::NotEquatable(ty) => vec![ty.clone()],
+            TypeError::DifferentLengths(left, right) => {
+                left.iter().chain(right.iter()).cloned().collect()
+            }
+            TypeError::KindMismatch(ty, expected, found) => {
+                vec![ty.clone(), expected.clone(), found.clone()]
+            }
+            TypeError::NotInScope(_, _)
+            | TypeError::UnknownLiteral(_)
+            | TypeError::TypeTooDeep(_, _) => vec![],
+        };
+
+        object(vec![
+            ("code", json_string(error.code())),
+            ("message", json_string(&error.to_string())),
+            ("types", array(&types)),
+        ])
+    }
+}
+
+// Variables as keys, sorted so the output doesn't change between runs
+impl ToJson for Substitution {
+    fn to_json(self: &Self) -> String {
+        let mut bindings: Vec<_> = self.iter().collect();
+        bindings.sort_by_key(|(variable, _)| *variable);
+
+        object(
+            bindings
+                .into_iter()
+                .map(|(variable, ty)| (variable.as_str(), ty.to_json()))
+                .collect(),
+        )
+    }
+}
+
+impl ToJson for TraceStep {
+    fn to_json(self: &Self) -> String {
+        let kind = match self.kind {
+            StepKind::Unify => "unify",
+            StepKind::Match => "match",
+        };
+        let (substitution, error) = match &self.result {
+            Ok(substitution) => (substitution.to_json(), "null".to_string()),
+            Err(error) => ("null".to_string(), error.to_json()),
+        };
+
+        object(vec![
+            ("step", json_string(kind)),
+            ("left", self.left.to_json()),
+            ("right", self.right.to_json()),
+            ("substitution", substitution),
+            ("error", error),
+            ("children", array(&self.children)),
+        ])
+    }
+}
+
+impl ToJson for Diagnostic {
+    fn to_json(self: &Self) -> String {
+        Diagnostic::to_json(self)
+    }
+}
+
+impl ToJson for LocatedError {
+    fn to_json(self: &Self) -> String {
+        object(vec![
+            ("error", self.error.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_get_escaped() {
+        assert_eq!(json_string("a\"b\\c\nd"), "\"a\\\"b\\\\c\\nd\"");
+        assert_eq!(json_string("\u{7}"), "\"\\u0007\"");
+        assert_eq!(json_string("ș"), "\"ș\"");
+    }
+
+    #[test]
+    fn nodes_say_what_they_are() {
+        let call = Ast::new_call(
+            Ast::Variable("f".to_string()),
+            Ast::StringLiteral("a".to_string()),
+        );
+
+        assert_eq!(
+            call.to_json(),
+            "{\"node\":\"call\",\"function\":{\"node\":\"variable\",\"name\":\"f\"},\"argument\":{\"node\":\"string\",\"value\":\"a\"}}"
+        );
+    }
+
+    // Kinds go all the way down to *, whose own kind is null
+    #[test]
+    fn types_carry_their_kinds() {
+        assert_eq!(
+            Type::number().to_json(),
+            "{\"constructor\":{\"name\":\"Number\",\"kind\":{\"constructor\":{\"name\":\"*\",\"kind\":null}}}}"
+        );
+    }
+}
@@ -0,0 +1,23 @@
+// The whole crate spells out self receivers (self: &Type)
+#![allow(clippy::needless_arbitrary_self_type)]
+
+pub mod api;
+pub mod bench;
+pub mod diagnostic;
+pub mod doc;
+pub mod dump;
+pub mod error_codes;
+pub mod format;
+pub mod fuzz;
+pub mod json;
+pub mod messages;
+pub mod minimize;
+pub mod parser;
+pub mod plugin;
+pub mod testing;
+pub mod type_checker;
+
+pub use api::{compile, compile_in, compile_with, infer_type, TypedProgram};
+pub use diagnostic::Diagnostic;
+pub use type_checker::explain::explain_type;
+pub use type_checker::type_::{Type, TypeCheckOptions, TypeContext};
@@ -1,42 +1,562 @@
-use std::io;
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+use std::{env, fs, process};
 
-mod parser;
-mod type_checker;
+use steiner::bench::{Benchmark, BENCHMARKS};
+use steiner::diagnostic::{self, check_source, check_source_with};
+use steiner::doc;
+use steiner::dump;
+use steiner::error_codes::{self, ERROR_CODES};
+use steiner::format::{format_expression, DEFAULT_WIDTH};
+use steiner::json::array;
+use steiner::messages::Locale;
+use steiner::minimize::minimize_failure;
+use steiner::parser;
+use steiner::plugin::{Command, Plugins};
+use steiner::testing;
+use steiner::type_checker::type_::{
+    get_type_and_notes_in, get_type_or_errors_in, get_typed_ast_in, Generalization, TypeContext,
+};
 
-use type_checker::type_::get_type_of;
+// How deeply nested the expressions check-properties generates are
+const PROPERTY_DEPTH: usize = 4;
 
-fn run(input: String) -> Result<(), String> {
-    let result = match parser::parse_expression(&input) {
-        Ok(value) => value,
-        Err(err) => return Err(format!("{}", err)),
+// Settings coming from command line flags
+struct Options {
+    // --no-prelude starts type checking from an empty environment
+    prelude: bool,
+    // --locale=ro picks the language of error messages
+    locale: Locale,
+    // --error-format=json prints diagnostics as json for editors and other tools
+    json: bool,
+    // --strict-kinds defaults unknown kinds to * and checks them again
+    strict_kinds: bool,
+    // --generalize=values only gives lets of syntactic values polymorphic types
+    generalization: Generalization,
+    // --trace prints every step of type checking to stderr
+    trace: bool,
+}
+
+impl Options {
+    fn context(self: &Options) -> TypeContext {
+        let context = if self.prelude {
+            TypeContext::new()
+        } else {
+            TypeContext::empty()
+        };
+
+        let context = context.with_generalization(self.generalization);
+        let context = if self.trace {
+            context.with_tracer(|event| eprintln!("{}", event))
+        } else {
+            context
+        };
+
+        if self.strict_kinds {
+            context.with_strict_kinds()
+        } else {
+            context
+        }
+    }
+
+    // What the REPL and steiner check run with
+    fn plugins(self: &Options) -> Plugins {
+        Plugins::default().with_command(TypeOf {
+            context: self.context(),
+            locale: self.locale,
+        })
+    }
+}
+
+// :type expression, printing only the type of an expression
+struct TypeOf {
+    context: TypeContext,
+    locale: Locale,
+}
+
+impl Command for TypeOf {
+    fn name(self: &TypeOf) -> &str {
+        "type"
+    }
+
+    fn run(self: &TypeOf, argument: &str) -> Result<String, String> {
+        let (ty, diagnostics) = check_source(argument, self.context.clone(), self.locale);
+
+        match ty {
+            Some(ty) => Ok(format!("{}", ty)),
+            None => {
+                let errors: Vec<_> = diagnostics
+                    .iter()
+                    .filter(|diagnostic| diagnostic.is_error())
+                    .map(|diagnostic| format!("{}", diagnostic))
+                    .collect();
+                Err(errors.join("\n\n"))
+            }
+        }
+    }
+}
+
+fn run(input: String, options: &Options) -> Result<(), String> {
+    let (result, syntax_errors) = parser::parse_expression_recovering(&input);
+    let mut messages: Vec<_> = syntax_errors.iter().map(|err| format!("{}", err)).collect();
+
+    // Whatever did parse still gets type checked, so every problem shows up at once
+    let result = match result {
+        Some(value) => value,
+        None => return Err(messages.join("\n\n")),
     };
 
-    println!("Finished parsing successfully");
-    // println!("{:?}", result);
+    if messages.is_empty() {
+        println!("Finished parsing successfully");
+    }
+
+    // Solving while inferring lets errors know which expression they came from
+    let context = options.context().with_eager_solving();
+
+    let (inferred, notes) = match get_type_or_errors_in(context, result) {
+        Ok(v) if messages.is_empty() => v,
+        Ok(_) => return Err(messages.join("\n\n")),
+        Err(errors) => {
+            messages.extend(errors.iter().map(|err| {
+                let mut lines = vec![err.message(options.locale)];
+                lines.extend(err.notes(options.locale));
+                let message = lines.join("\n");
 
-    let inferred = match get_type_of(result) {
-        Ok(v) => v,
-        Err(err) => return Err(format!("{}", err)),
+                match err.span {
+                    Some(span) => format!(
+                        "Type error at offsets {}-{}: {}",
+                        span.start, span.end, message
+                    ),
+                    None => message,
+                }
+            }));
+            return Err(messages.join("\n\n"));
+        }
     };
 
     println!("Finished type-checking successfully!\n");
+
+    for note in notes {
+        println!("{}\n", note);
+    }
+
     println!("The expression has type {}", inferred);
 
-    return Ok(());
+    Ok(())
 }
 
-fn main() {
+// Read a whole file, or stdin when there's no path
+fn read_source(path: Option<&String>) -> Result<String, String> {
+    match path {
+        Some(path) => fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err)),
+        None => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .map_err(|err| format!("{}", err))?;
+            Ok(input)
+        }
+    }
+}
+
+// steiner fmt [file]
+fn format(path: Option<&String>) -> Result<(), String> {
+    let source = read_source(path)?;
+    let ast = parser::parse_expression(&source).map_err(|err| format!("{}", err))?;
+
+    println!("{}", format_expression(&ast, DEFAULT_WIDTH));
+
+    Ok(())
+}
+
+// steiner check [file]
+fn check(path: Option<&String>, options: &Options) -> Result<(), String> {
+    let source = read_source(path)?;
+    let (ty, diagnostics) = check_source_with(
+        &source,
+        options.context(),
+        options.locale,
+        &options.plugins(),
+    );
+
+    if options.json {
+        println!("{}", diagnostic::to_json(&diagnostics));
+    } else {
+        for diagnostic in &diagnostics {
+            println!("{}\n", diagnostic);
+        }
+
+        if let Some(ty) = &ty {
+            println!("The expression has type {}", ty);
+        }
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+
+    if errors == 0 {
+        Ok(())
+    } else if options.json {
+        // The json already says everything, so only the exit code is left to set
+        process::exit(1)
+    } else {
+        Err(format!("Found {} errors", errors))
+    }
+}
+
+// steiner explain [code], listing every code when none is given
+fn explain(code: Option<&String>) -> Result<(), String> {
+    match code {
+        Some(code) => {
+            let entry = error_codes::lookup(code)
+                .ok_or_else(|| format!("There is no error with code {}", code))?;
+
+            println!("{} ({})\n\n{}", entry.code, entry.name, entry.explanation);
+        }
+        None => {
+            for entry in ERROR_CODES {
+                println!("{} {}", entry.code, entry.name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// steiner dump --stage=ast|typed-ast|constraints|subst|trace [file]
+fn dump(path: Option<&String>, stage: Option<&str>, options: &Options) -> Result<(), String> {
+    let source = read_source(path)?;
+    let ast = parser::parse_expression(&source).map_err(|err| format!("{}", err))?;
+
+    match stage {
+        Some("ast") => println!("{}", dump::ast(&ast)),
+        Some("typed-ast") => {
+            let typed = get_typed_ast_in(options.context(), ast).map_err(|err| err.to_string())?;
+            println!("{}", dump::typed_ast(&typed));
+        }
+        // Constraints of lets get solved before the rest, so only what they found out shows up
+        Some("constraints") => {
+            let mut context = options.context();
+            context.infer(ast).map_err(|err| err.to_string())?;
+
+            for constraint in context.pending_constraints() {
+                println!("{}", constraint);
+            }
+        }
+        // Every unification step, or json for them with --error-format=json
+        Some("trace") => {
+            let mut context = options.context().with_tracing();
+            context.infer(ast).map_err(|err| err.to_string())?;
+            let result = context.solve_constraints();
+            let steps = context.solver_trace();
+
+            if options.json {
+                println!("{}", array(&steps));
+            } else {
+                for step in &steps {
+                    println!("{}", step.render());
+                }
+            }
+
+            result.map_err(|err| err.to_string())?;
+        }
+        Some("subst") => {
+            let mut context = options.context();
+            context.infer(ast).map_err(|err| err.to_string())?;
+            let substitution = context.solve_constraints().map_err(|err| err.to_string())?;
+
+            // Shorter names first, so t2 comes before t10
+            let mut entries: Vec<_> = substitution.into_iter().collect();
+            entries.sort_by(|(left, _), (right, _)| {
+                left.len().cmp(&right.len()).then(left.cmp(right))
+            });
+
+            for (variable, ty) in entries {
+                println!("{} = {}", variable, ty);
+            }
+        }
+        Some(other) => return Err(format!("Unknown stage {}", other)),
+        None => {
+            return Err(
+                "steiner dump needs --stage=ast|typed-ast|constraints|subst|trace".to_string(),
+            )
+        }
+    }
+
+    Ok(())
+}
+
+// steiner check-properties [count] --seed=n, formatting and substituting random inputs
+fn check_properties(count: Option<&String>, seed: Option<&str>) -> Result<(), String> {
+    let count = match count {
+        Some(count) => count
+            .parse()
+            .map_err(|_| format!("{} is not a number of inputs", count))?,
+        None => 100,
+    };
+    let seed = match seed {
+        Some(seed) => seed
+            .parse()
+            .map_err(|_| format!("{} is not a valid seed", seed))?,
+        None => 0,
+    };
+
+    let failures = testing::check_properties(seed, count, PROPERTY_DEPTH);
+
+    for failure in &failures {
+        println!("{}\n", failure);
+    }
+
+    if failures.is_empty() {
+        println!("Checked {} random inputs", count);
+        Ok(())
+    } else {
+        Err(format!("{} properties failed", failures.len()))
+    }
+}
+
+// steiner bench [name] --size=n --iterations=n, timing type checking on generated programs
+fn run_benchmarks(
+    name: Option<&String>,
+    size: Option<&str>,
+    iterations: Option<&str>,
+) -> Result<(), String> {
+    let benchmarks: Vec<_> = match name {
+        Some(name) => vec![BENCHMARKS
+            .iter()
+            .find(|benchmark| benchmark.name == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = BENCHMARKS.iter().map(|benchmark| benchmark.name).collect();
+                format!(
+                    "Unknown benchmark {}, try one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })?],
+        None => BENCHMARKS.iter().collect(),
+    };
+    let size = match size {
+        Some(size) => Some(
+            size.parse()
+                .map_err(|_| format!("{} is not a valid size", size))?,
+        ),
+        None => None,
+    };
+    let iterations = match iterations {
+        Some(iterations) => iterations
+            .parse()
+            .map_err(|_| format!("{} is not a number of runs", iterations))?,
+        None => 10,
+    };
+
+    for benchmark in benchmarks {
+        let size = size.unwrap_or(benchmark.size);
+        let average = measure(benchmark, size, iterations)?;
+
+        println!(
+            "{} (size {}): {:.3}ms per run over {} runs",
+            benchmark.name,
+            size,
+            average.as_secs_f64() * 1000.0,
+            iterations.max(1)
+        );
+    }
+
+    Ok(())
+}
+
+// How long type checking the program of the given size takes on average, with the prelude in
+// scope. Generating the program isn't part of the time
+fn measure(benchmark: &Benchmark, size: usize, iterations: usize) -> Result<Duration, String> {
+    let program = (benchmark.program)(size);
+    let iterations = iterations.max(1);
+    let mut total = Duration::new(0, 0);
+
+    for _ in 0..iterations {
+        let program = program.clone();
+        let start = Instant::now();
+        let result = get_type_and_notes_in(TypeContext::new(), program);
+        total += start.elapsed();
+
+        result.map_err(|error| format!("{} doesn't type check: {}", benchmark.name, error))?;
+    }
+
+    Ok(total / iterations as u32)
+}
+
+// steiner minimize [file]
+fn minimize(path: Option<&String>) -> Result<(), String> {
+    let source = read_source(path)?;
+    let ast = parser::parse_expression(&source).map_err(|err| format!("{}", err))?;
+    let minimized = minimize_failure(ast)?;
+
+    println!("{}", format_expression(&minimized, DEFAULT_WIDTH));
+
+    Ok(())
+}
+
+// steiner doc --check-examples [file]
+fn check_examples(path: Option<&String>) -> Result<(), String> {
+    let source = read_source(path)?;
+    let examples = doc::examples(&source)?;
+    let mut failed = 0;
+
+    for example in &examples {
+        if let Err(message) = doc::check_example(example) {
+            failed += 1;
+            println!("Example on line {} failed:\n{}\n", example.line, message);
+        }
+    }
+
+    println!("{} examples, {} failed", examples.len(), failed);
+
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(format!("{} examples failed", failed))
+    }
+}
+
+// Returns None at the end of the input
+fn read_line() -> Option<String> {
+    let mut input = String::new();
+    let read = io::stdin()
+        .read_line(&mut input)
+        .expect("Error reading line");
+
+    if read == 0 {
+        None
+    } else {
+        Some(input)
+    }
+}
+
+// Everything between :{ and :} is read as one input
+fn read_block() -> String {
+    let mut block = String::new();
+
+    while let Some(line) = read_line() {
+        if line.trim() == ":}" {
+            break;
+        }
+
+        block.push_str(&line);
+    }
+
+    block
+}
+
+fn repl(options: &Options, plugins: &Plugins) {
     loop {
         println!("Enter a string to lex:");
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Error reading line");
+        let input = match read_line() {
+            Some(line) if line.trim() == ":{" => read_block(),
+            Some(line) => line,
+            None => break,
+        };
         println!();
 
-        if let Err(message) = run(input) {
+        if let Some(result) = plugins.run_command(&input) {
+            match result {
+                Ok(output) => println!("{}", output),
+                Err(message) => println!("{}", message),
+            }
+        } else if let Err(message) = run(input, options) {
             println!("\n{}", message)
         }
         println!()
     }
 }
+
+// Deep programs take a lot of stack to parse and check, more than the main thread has in
+// debug builds
+fn main() {
+    parser::with_stack(cli)
+}
+
+fn cli() {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+
+    let mut options = Options {
+        prelude: true,
+        locale: Locale::default(),
+        json: false,
+        strict_kinds: false,
+        generalization: Generalization::default(),
+        trace: false,
+    };
+
+    for flag in &flags {
+        match flag.as_str() {
+            "--no-prelude" => options.prelude = false,
+            "--strict-kinds" => options.strict_kinds = true,
+            "--trace" => options.trace = true,
+            "--check-examples" => {}
+            flag if ["--stage=", "--seed=", "--size=", "--iterations="]
+                .iter()
+                .any(|prefix| flag.starts_with(prefix)) => {}
+            flag if flag.starts_with("--locale=") => {
+                let name = &flag["--locale=".len()..];
+
+                options.locale = Locale::parse(name).unwrap_or_else(|| {
+                    eprintln!("Unknown locale {}", name);
+                    process::exit(1);
+                });
+            }
+            flag if flag.starts_with("--generalize=") => {
+                let name = &flag["--generalize=".len()..];
+
+                options.generalization = Generalization::parse(name).unwrap_or_else(|| {
+                    eprintln!("Unknown generalization policy {}", name);
+                    process::exit(1);
+                });
+            }
+            "--error-format=json" => options.json = true,
+            "--error-format=human" => options.json = false,
+            flag => {
+                eprintln!("Unknown flag {}", flag);
+                process::exit(1);
+            }
+        }
+    }
+
+    let result = match args.first().map(String::as_str) {
+        None => {
+            repl(&options, &options.plugins());
+            Ok(())
+        }
+        Some("check") => check(args.get(1), &options),
+        Some("explain") => explain(args.get(1)),
+        Some("fmt") => format(args.get(1)),
+        Some("dump") => {
+            let stage = flags.iter().find_map(|flag| flag.strip_prefix("--stage="));
+
+            dump(args.get(1), stage, &options)
+        }
+        Some("minimize") => minimize(args.get(1)),
+        Some("check-properties") => {
+            let seed = flags.iter().find_map(|flag| flag.strip_prefix("--seed="));
+
+            check_properties(args.get(1), seed)
+        }
+        Some("bench") => {
+            let size = flags.iter().find_map(|flag| flag.strip_prefix("--size="));
+            let iterations = flags
+                .iter()
+                .find_map(|flag| flag.strip_prefix("--iterations="));
+
+            run_benchmarks(args.get(1), size, iterations)
+        }
+        // Checking examples is the only thing doc does so far
+        Some("doc") if flags.iter().any(|flag| flag == "--check-examples") => {
+            check_examples(args.get(1))
+        }
+        Some("doc") => Err("steiner doc needs --check-examples".to_string()),
+        Some(other) => Err(format!("Unknown command {}", other)),
+    };
+
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
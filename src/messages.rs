@@ -0,0 +1,144 @@
+// Language diagnostics get shown in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    English,
+    Romanian,
+}
+
+impl Locale {
+    // Parse a locale name such as "en" or "ro"
+    pub fn parse(name: &str) -> Option<Locale> {
+        match name {
+            "en" => Some(Locale::English),
+            "ro" => Some(Locale::Romanian),
+            _ => None,
+        }
+    }
+}
+
+// The text of a message with {0}, {1}, ... where its arguments go.
+// Messages missing from a locale fall back to english
+fn template(locale: Locale, key: &str) -> &'static str {
+    let translated = match locale {
+        Locale::English => None,
+        Locale::Romanian => romanian(key),
+    };
+
+    translated
+        .or_else(|| english(key))
+        .unwrap_or("Unknown message")
+}
+
+fn english(key: &str) -> Option<&'static str> {
+    Some(match key {
+        "E0001" => "Cannot unify type\n    {0}\nwith type\n    {1}",
+        "E0002" => "Type variable {0} would have to contain itself:\n    {0} = {1}\n    {2}",
+        "E0003" => "Cannot match type\n    {0}\nwith type\n    {1}",
+        "E0004" => "Variable {0} is not in scope",
+        "E0005" => "Conflicting substitutions: \n    {0} = {1}\nand\n    {0} = {2}",
+        "E0006" => {
+            "Cannot match length {0} with {1} while trying to unify types\n    {2}\nwith\n    {3}"
+        }
+        "E0007" => "Type\n    {0}\nhas kind {2}, but kind {1} is needed here",
+        "too-many-arguments" => "{0} is applied to too many arguments",
+        "missing-argument" => "{0} is missing an argument",
+        "missing-arguments" => "{0} is missing {1} arguments",
+        "E0008" => {
+            "Type variable {0} from an annotation escapes it, so it can't stand for any type"
+        }
+        "E0009" => "Unknown literal #{0}",
+        "E0010" => {
+            "Type variable {0} would stand for a type nested {1} levels deep, more than the {2} allowed"
+        }
+        "E0011" => "Values of type\n    {0}\ncan't be compared for equality, since they might contain functions",
+        "did-you-mean" => "Did you mean {0}?",
+        "type-difference" => "{0} vs {1} inside {2}",
+        "reason-if-condition" => "The condition of an if has to be a Boolean",
+        "reason-if-branches" => "Both branches of an if have to have the same type",
+        "reason-application" => "A function has to accept the argument it is called with",
+        "reason-annotation" => "An expression has to be at least as general as its annotation",
+        "reason-let" => "A let has to agree with how its value uses variables from outside",
+        "reason-kind" => "A type has to take the argument it is applied to",
+        _ => return None,
+    })
+}
+
+fn romanian(key: &str) -> Option<&'static str> {
+    Some(match key {
+        "E0001" => "Tipul\n    {0}\nnu poate fi unificat cu tipul\n    {1}",
+        "E0002" => "Variabila de tip {0} ar trebui să se conțină pe sine:\n    {0} = {1}\n    {2}",
+        "E0003" => "Tipul\n    {0}\nnu se potrivește cu tipul\n    {1}",
+        "E0004" => "Variabila {0} nu este definită",
+        "E0005" => "Substituții în conflict: \n    {0} = {1}\nși\n    {0} = {2}",
+        "E0006" => {
+            "Lungimea {0} nu se potrivește cu {1} la unificarea tipurilor\n    {2}\ncu\n    {3}"
+        }
+        "E0007" => "Tipul\n    {0}\nare kind-ul {2}, dar aici e nevoie de kind-ul {1}",
+        "too-many-arguments" => "{0} primește prea multe argumente",
+        "missing-argument" => "Lui {0} îi lipsește un argument",
+        "missing-arguments" => "Lui {0} îi lipsesc {1} argumente",
+        "E0008" => "Variabila de tip {0} iese din adnotarea ei, deci nu poate reprezenta orice tip",
+        "E0009" => "Literal necunoscut #{0}",
+        "E0010" => {
+            "Variabila de tip {0} ar reprezenta un tip imbricat pe {1} niveluri, mai mult decât cele {2} permise"
+        }
+        "E0011" => "Valorile de tip\n    {0}\nnu pot fi comparate, deoarece pot conține funcții",
+        "did-you-mean" => "Te refereai la {0}?",
+        "type-difference" => "{0} față de {1} în {2}",
+        "reason-if-condition" => "Condiția unui if trebuie să fie Boolean",
+        "reason-if-branches" => "Ambele ramuri ale unui if trebuie să aibă același tip",
+        "reason-application" => "O funcție trebuie să accepte argumentul cu care e apelată",
+        "reason-annotation" => "O expresie trebuie să fie cel puțin la fel de generală ca adnotarea ei",
+        "reason-let" => "Un let trebuie să fie de acord cu felul în care valoarea lui folosește variabile din afară",
+        "reason-kind" => "Un tip trebuie să accepte argumentul la care e aplicat",
+        _ => return None,
+    })
+}
+
+// Fill in the arguments of a message
+pub fn render(locale: Locale, key: &str, arguments: &[String]) -> String {
+    let mut message = template(locale, key).to_string();
+
+    for (index, argument) in arguments.iter().enumerate() {
+        message = message.replace(&format!("{{{}}}", index), argument);
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locales_parse_from_their_names() {
+        assert_eq!(Locale::parse("en"), Some(Locale::English));
+        assert_eq!(Locale::parse("ro"), Some(Locale::Romanian));
+        assert_eq!(Locale::parse("fr"), None);
+    }
+
+    // Arguments can show up more than once, like the variable of a recursive type
+    #[test]
+    fn arguments_fill_in_their_placeholders() {
+        let arguments = ["a".to_string(), "List a".to_string(), "".to_string()];
+
+        assert_eq!(
+            render(Locale::English, "E0002", &arguments),
+            "Type variable a would have to contain itself:\n    a = List a\n    "
+        );
+        assert_eq!(
+            render(Locale::Romanian, "E0004", &["x".to_string()]),
+            "Variabila x nu este definită"
+        );
+    }
+
+    #[test]
+    fn unknown_messages_fall_back() {
+        assert_eq!(romanian("not-a-message"), None);
+        assert_eq!(
+            render(Locale::Romanian, "not-a-message", &[]),
+            "Unknown message"
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use crate::parser::Ast;
+use crate::type_checker::type_::{get_type_of, TypeError};
+use std::mem::{discriminant, Discriminant};
+use std::panic::{self, AssertUnwindSafe};
+
+// What went wrong when checking a program, precise enough to tell if a smaller one goes wrong the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    Panic,
+    Error(Discriminant<TypeError>),
+}
+
+// Type check without letting panics escape
+pub fn failure(ast: &Ast) -> Option<Failure> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| get_type_of(ast.clone())));
+
+    match result {
+        Err(_) => Some(Failure::Panic),
+        Ok(Err(error)) => Some(Failure::Error(discriminant(&error))),
+        Ok(Ok(_)) => None,
+    }
+}
+
+// Locations and comments only get in the way of shrinking
+fn strip(ast: Ast) -> Ast {
+    match ast {
+        Ast::Located(_, inner) | Ast::Commented(_, inner) => strip(*inner),
+        other => other.map_children(&mut strip),
+    }
+}
+
+// Expressions one step smaller than the given one: either a child taking the place of
+// its parent, or the same node with one child shrunk
+fn shrinks(ast: &Ast) -> Vec<Ast> {
+    let mut result: Vec<Ast> = ast.children().into_iter().cloned().collect();
+
+    for index in 0..ast.children().len() {
+        for smaller in shrinks(ast.children()[index]) {
+            let mut position = 0;
+            let mut smaller = Some(smaller);
+
+            result.push(ast.clone().map_children(&mut |child| {
+                position += 1;
+
+                if position - 1 == index {
+                    smaller.take().unwrap()
+                } else {
+                    child
+                }
+            }));
+        }
+    }
+
+    result
+}
+
+// Keep shrinking as long as the predicate holds, until no single step keeps it holding
+pub fn minimize(ast: Ast, predicate: impl Fn(&Ast) -> bool) -> Ast {
+    let mut current = strip(ast);
+
+    while let Some(smaller) = shrinks(&current)
+        .into_iter()
+        .find(|smaller| predicate(smaller))
+    {
+        current = smaller;
+    }
+
+    current
+}
+
+// Shrink a program while it keeps failing the same way it does now
+pub fn minimize_failure(ast: Ast) -> Result<Ast, String> {
+    let expected =
+        failure(&ast).ok_or("The program type checks fine, so there is nothing to minimize")?;
+
+    // Candidates are expected to panic, and the messages would drown everything else
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let minimized = minimize(ast, |candidate| failure(candidate) == Some(expected));
+    panic::set_hook(hook);
+
+    Ok(minimized)
+}
@@ -1,16 +1,154 @@
 use crate::type_checker::type_::{Type, VarName};
+use std::cell::{Cell, RefCell};
+use std::cmp::max;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
 use std::vec::Vec;
+use std::{panic, thread};
+
+// Byte offsets of the source a node was parsed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn contains(self: &Span, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
+    pub fn len(self: &Span) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(self: &Span) -> bool {
+        self.start == self.end
+    }
+}
+
+// A `-- line` or `{- block -}` comment, delimiters included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub span: Span,
+    pub text: String,
+}
+
+impl Comment {
+    // Line comments run until the end of the line, so whatever follows must go on a new one
+    pub fn is_line_comment(self: &Comment) -> bool {
+        self.text.starts_with("--")
+    }
+}
+
+// Comments right before and right after an expression
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trivia {
+    pub leading: Vec<Comment>,
+    pub trailing: Vec<Comment>,
+}
+
+impl Trivia {
+    pub fn is_empty(self: &Trivia) -> bool {
+        self.leading.is_empty() && self.trailing.is_empty()
+    }
+}
+
+// How deeply expressions and types can be nested. Parsing them, and everything done with the
+// tree afterwards, recurses once per level, so deeper programs get rejected instead of
+// running out of stack
+pub const MAX_NESTING: usize = 500;
+
+// Enough stack for MAX_NESTING levels with room to spare, even in debug builds, which take
+// several times more per level. Anything parsing or checking untrusted input should run on
+// a thread this big
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+// Run something on a thread with STACK_SIZE of stack, passing its panics along
+pub fn with_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let thread = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(f)
+        .expect("Error starting a thread");
+
+    match thread.join() {
+        Ok(result) => result,
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
+
+// A line inside a do block
+#[derive(Debug, Clone)]
+pub enum Statement {
+    // x <- action
+    Bind(String, Ast),
+    // Runs an action, throwing away its result
+    Run(Ast),
+}
+
+// Infix operators, which all stand for calls to functions in scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    // x |> f
+    Pipe,
+    // f >> g, running f first
+    ComposeForward,
+    // g << f, running f first as well
+    ComposeBackward,
+    // a == b
+    Equal,
+    // a /= b
+    NotEqual,
+}
+
+impl Operator {
+    pub fn symbol(self: &Operator) -> &'static str {
+        match self {
+            Operator::Pipe => "|>",
+            Operator::ComposeForward => ">>",
+            Operator::ComposeBackward => "<<",
+            Operator::Equal => "==",
+            Operator::NotEqual => "/=",
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum Ast {
     Variable(String),
     FloatLiteral(f64),
     StringLiteral(String),
+    CharLiteral(char),
+    // `#` followed by text only the embedder knows how to make sense of
+    CustomLiteral(String),
     If(Box<Ast>, Box<Ast>, Box<Ast>),
     Let(String, Box<Ast>, Box<Ast>),
     FunctionCall(Box<Ast>, Box<Ast>),
     Lambda(String, Box<Ast>),
     Annotation(Box<Ast>, Type),
+    // A binding the host provides, which only has a declared type
+    Foreign(String, Type, Box<Ast>),
+    // do { x <- action; ...; result }
+    Do(Vec<Statement>, Box<Ast>),
+    // left op right, kept apart from calls so it can be printed back the way it was written
+    Operator(Operator, Box<Ast>, Box<Ast>),
+    // -x, which is negate x
+    Negation(Box<Ast>),
+    // if | condition -> value | ... | otherwise -> value
+    MultiWayIf(Vec<(Ast, Ast)>, Box<Ast>),
+    // Source which couldn't be parsed, kept around when recovering from syntax errors
+    Error(String),
+    // `_` or `?name`, left for the type checker to fill in. Holds the offset it was written at
+    Hole(usize, Option<String>),
+    // Remembers where in the source the wrapped expression came from
+    Located(Span, Box<Ast>),
+    // Comments attached to the wrapped expression
+    Commented(Trivia, Box<Ast>),
 }
 
 impl Ast {
@@ -23,6 +161,10 @@ impl Ast {
         Ast::Let(name, Box::new(value), Box::new(body))
     }
 
+    pub fn new_foreign(name: String, ty: Type, body: Ast) -> Ast {
+        Ast::Foreign(name, ty, Box::new(body))
+    }
+
     pub fn new_call(function: Ast, argument: Ast) -> Ast {
         Ast::FunctionCall(Box::new(function), Box::new(argument))
     }
@@ -31,6 +173,10 @@ impl Ast {
         Ast::Lambda(name, Box::new(body))
     }
 
+    pub fn new_operator(operator: Operator, left: Ast, right: Ast) -> Ast {
+        Ast::Operator(operator, Box::new(left), Box::new(right))
+    }
+
     pub fn call_chain(self: Ast, arguments: Vec<Ast>) -> Ast {
         let mut result = self;
 
@@ -51,6 +197,188 @@ impl Ast {
         result
     }
 
+    // Turn a do block into calls to bind. Results which get thrown away are bound to _,
+    // a name no user written variable can have
+    pub fn desugar_do(statements: Vec<Statement>, result: Ast) -> Ast {
+        statements
+            .into_iter()
+            .rev()
+            .fold(result, |rest, statement| {
+                let (name, action) = match statement {
+                    Statement::Bind(name, action) => (name, action),
+                    Statement::Run(action) => ("_".to_string(), action),
+                };
+
+                Ast::Variable("bind".to_string())
+                    .call_chain(vec![action, Ast::new_lambda(name, rest)])
+            })
+    }
+
+    // The call an operator stands for. x |> f is the same as f x, f >> g and g << f both
+    // mean compose g f, and a == b and a /= b are eq a b and neq a b. Like do blocks and bind,
+    // these use whichever functions with those names are in scope
+    pub fn desugar_operator(operator: Operator, left: Ast, right: Ast) -> Ast {
+        let call = |name: &str, arguments| Ast::Variable(name.to_string()).call_chain(arguments);
+
+        match operator {
+            Operator::Pipe => Ast::new_call(right, left),
+            Operator::ComposeForward => call("compose", vec![right, left]),
+            Operator::ComposeBackward => call("compose", vec![left, right]),
+            Operator::Equal => call("eq", vec![left, right]),
+            Operator::NotEqual => call("neq", vec![left, right]),
+        }
+    }
+
+    // if | c1 -> e1 | c2 -> e2 | otherwise -> e3 is the same as
+    // if c1 then e1 else if c2 then e2 else e3
+    pub fn desugar_multi_way_if(branches: Vec<(Ast, Ast)>, otherwise: Ast) -> Ast {
+        branches
+            .into_iter()
+            .rev()
+            .fold(otherwise, |rest, (condition, value)| {
+                Ast::new_if(condition, value, rest)
+            })
+    }
+
+    // Syntactic values can't do anything when evaluated, like lambdas and literals.
+    // Those are the lets the value restriction still generalizes
+    pub fn is_value(self: &Ast) -> bool {
+        match self {
+            Ast::Variable(_)
+            | Ast::FloatLiteral(_)
+            | Ast::StringLiteral(_)
+            | Ast::CharLiteral(_)
+            | Ast::CustomLiteral(_)
+            | Ast::Lambda(_, _)
+            | Ast::Hole(_, _)
+            | Ast::Error(_) => true,
+            Ast::Let(_, value, body) => value.is_value() && body.is_value(),
+            Ast::Foreign(_, _, inner)
+            | Ast::Annotation(inner, _)
+            | Ast::Located(_, inner)
+            | Ast::Commented(_, inner) => inner.is_value(),
+            Ast::If(_, _, _)
+            | Ast::FunctionCall(_, _)
+            | Ast::Do(_, _)
+            | Ast::Operator(_, _, _)
+            | Ast::Negation(_)
+            | Ast::MultiWayIf(_, _) => false,
+        }
+    }
+
+    // The direct subexpressions of this node, in source order
+    pub fn children(self: &Ast) -> Vec<&Ast> {
+        match self {
+            Ast::If(condition, left, right) => vec![condition, left, right],
+            Ast::Let(_, value, body) => vec![value, body],
+            Ast::FunctionCall(function, argument) => vec![function, argument],
+            Ast::Operator(_, left, right) => vec![left, right],
+            Ast::Do(statements, result) => statements
+                .iter()
+                .map(|statement| match statement {
+                    Statement::Bind(_, action) | Statement::Run(action) => action,
+                })
+                .chain(std::iter::once(&**result))
+                .collect(),
+            Ast::MultiWayIf(branches, otherwise) => branches
+                .iter()
+                .flat_map(|(condition, value)| vec![condition, value])
+                .chain(std::iter::once(&**otherwise))
+                .collect(),
+            Ast::Lambda(_, inner)
+            | Ast::Negation(inner)
+            | Ast::Annotation(inner, _)
+            | Ast::Foreign(_, _, inner)
+            | Ast::Located(_, inner)
+            | Ast::Commented(_, inner) => vec![inner],
+            _ => vec![],
+        }
+    }
+
+    // How many nodes deep the tree goes. Walks the tree with a stack of its own,
+    // since it has to work on trees too deep to recurse on
+    pub fn depth(self: &Ast) -> usize {
+        let mut deepest = 0;
+        let mut stack = vec![(self, 1)];
+
+        while let Some((ast, depth)) = stack.pop() {
+            deepest = max(deepest, depth);
+            stack.extend(ast.children().into_iter().map(|child| (child, depth + 1)));
+        }
+
+        deepest
+    }
+
+    // Dropping a tree recurses once per level as well, so this takes it apart one node at a time
+    pub fn discard(self: Ast) {
+        let mut stack = vec![self];
+
+        while let Some(ast) = stack.pop() {
+            ast.map_children(&mut |child| {
+                stack.push(child);
+                Ast::Error(String::new())
+            });
+        }
+    }
+
+    // Rebuild this node with every child replaced, visiting them in source order
+    pub fn map_children(self: Ast, f: &mut impl FnMut(Ast) -> Ast) -> Ast {
+        let mut map = |ast: Box<Ast>| Box::new(f(*ast));
+
+        match self {
+            Ast::If(condition, left, right) => {
+                let condition = map(condition);
+                let left = map(left);
+                Ast::If(condition, left, map(right))
+            }
+            Ast::Let(name, value, body) => {
+                let value = map(value);
+                Ast::Let(name, value, map(body))
+            }
+            Ast::FunctionCall(function, argument) => {
+                let function = map(function);
+                Ast::FunctionCall(function, map(argument))
+            }
+            Ast::Operator(operator, left, right) => {
+                let left = map(left);
+                Ast::Operator(operator, left, map(right))
+            }
+            Ast::Do(statements, result) => {
+                let statements = statements
+                    .into_iter()
+                    .map(|statement| match statement {
+                        Statement::Bind(name, action) => Statement::Bind(name, f(action)),
+                        Statement::Run(action) => Statement::Run(f(action)),
+                    })
+                    .collect();
+
+                Ast::Do(statements, Box::new(f(*result)))
+            }
+            Ast::MultiWayIf(branches, otherwise) => {
+                let branches = branches
+                    .into_iter()
+                    .map(|(condition, value)| {
+                        let condition = f(condition);
+                        (condition, f(value))
+                    })
+                    .collect();
+
+                Ast::MultiWayIf(branches, Box::new(f(*otherwise)))
+            }
+            Ast::Lambda(name, body) => Ast::Lambda(name, map(body)),
+            Ast::Negation(inner) => Ast::Negation(map(inner)),
+            Ast::Annotation(annotated, annotation) => Ast::Annotation(map(annotated), annotation),
+            Ast::Foreign(name, ty, body) => Ast::Foreign(name, ty, map(body)),
+            Ast::Located(span, ast) => Ast::Located(span, map(ast)),
+            Ast::Commented(trivia, ast) => Ast::Commented(trivia, map(ast)),
+            leaf => leaf,
+        }
+    }
+
+    pub fn locate(self: Ast, start: usize, end: usize) -> Ast {
+        Ast::Located(Span::new(start, end), Box::new(self))
+    }
+
     // annotate an expression with a type
     pub fn annotate(self: Ast, annotation: Type) -> Ast {
         Ast::Annotation(Box::new(self), annotation)
@@ -68,21 +396,92 @@ impl Ast {
     }
 }
 
+// A piece of the source which couldn't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Display for SyntaxError {
+    fn fmt(self: &Self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Syntax error at offsets {}-{}: {}",
+            self.span.start, self.span.end, self.message
+        )
+    }
+}
+
+type SyntaxErrors = RefCell<BTreeMap<usize, SyntaxError>>;
+
+// Turn input the grammar gave up on into an error node, when recovery is turned on
+fn recovered(
+    errors: Option<&SyntaxErrors>,
+    start: usize,
+    end: usize,
+    text: &str,
+) -> Result<Ast, &'static str> {
+    let errors = errors.ok_or("valid syntax")?;
+
+    errors.borrow_mut().entry(start).or_insert(SyntaxError {
+        span: Span::new(start, end),
+        message: format!("could not make sense of {:?}", text.trim()),
+    });
+
+    Ok(Ast::Error(text.to_string()))
+}
+
+// Peg only takes static strings, so this repeats MAX_NESTING
+const NESTED_TOO_DEEPLY: &str = "something nested at most 500 levels deep";
+
 // If this is true the string cannot be used as a variable name and stuff
+
 fn is_reserved(input: &str) -> bool {
-    common_macros::hash_set!["if", "then", "else", "let", "in", "forall"].contains(input)
+    common_macros::hash_set!["if", "then", "else", "let", "in", "forall", "foreign", "import", "do"]
+        .contains(input)
 }
 
 peg::parser! {
-    grammar parse() for str {
-        rule whitespace() = quiet!{[' ' | '\n' | '\t']}
+    // Comments are skipped like whitespace, but also get collected by their start offset
+    // When given somewhere to put errors, bad input inside parenthesis, bad statements and
+    // trailing garbage get skipped instead of failing the whole parse.
+    // Depth counts how many expressions and types we are inside of right now
+    grammar parse(comments: &RefCell<BTreeMap<usize, Comment>>, errors: Option<&SyntaxErrors>, depth: &Cell<usize>) for str {
+        rule line_comment() = "--" (!"\n" [_])*
+        // Cached, since an unclosed comment would otherwise get rescanned from every nested opening
+        #[cache]
+        rule block_comment() = "{-" (block_comment() / !"-}" [_])* "-}"
+        rule comment()
+            = start:position!() text:$(line_comment() / block_comment()) end:position!() {
+                comments.borrow_mut().entry(start).or_insert(Comment {
+                    span: Span::new(start, end),
+                    text: text.to_string(),
+                });
+             }
+
+        // Every enter() needs a leave() after it, even when what's in between fails
+        rule enter()
+            = {?
+                if depth.get() < MAX_NESTING {
+                    depth.set(depth.get() + 1);
+                    Ok(())
+                } else {
+                    Err(NESTED_TOO_DEEPLY)
+                }
+             }
+
+        rule leave() = { depth.set(depth.get() - 1) }
+
+        rule whitespace() = quiet!{[' ' | '\n' | '\t'] / comment()}
         rule _ () = whitespace()*
         rule __ () = whitespace()+
         rule alphanumeric() -> String
             = s:$['a'..='z' | 'A'..='Z' | '0'..='9'] { s.to_string() }
 
+        // Names can't start with ' so they don't get confused with characters
         rule variable_name() -> String
-            = name:$((alphanumeric() / "'" / "*")+) {?
+            = name:$((alphanumeric() / "*") (alphanumeric() / "'" / "*")*) {?
                 if is_reserved(name) {
                     Err("Keywords cannot be used as identifiers")
                 } else {
@@ -94,26 +493,60 @@ peg::parser! {
             = name:variable_name() { Ast::Variable(name) }
 
         rule number() -> Ast
-            = n:$(['0'..='9']+) { Ast::FloatLiteral(n.parse().unwrap()) }
-
-        rule escape() -> String
-            = "\\" character:$("\"" / "/" / "n" / "r" / "t") {
-                match character {
-                    "n" => "\n",
-                    "r" => "\r",
-                    "t" => "\t",
-                    _ => character,
-                }.to_string()
-            }
+            = n:$("-"? ['0'..='9']+) { Ast::FloatLiteral(n.parse().unwrap()) }
+
+        // -f x negates the whole call. As an argument it only takes the atom right after it,
+        // so f -x passes -x to f. Two dashes start a comment and -1 is a number
+        rule negation() -> Ast
+            = "-" !("-" / ['0'..='9']) operand:atom() { Ast::Negation(Box::new(operand)) }
+
+        rule negated_call() -> Ast
+            = start:position!() "-" !("-" / ['0'..='9']) operand:call() end:position!() {
+                Ast::Negation(Box::new(operand)).locate(start, end)
+             }
+
+        rule escape() -> char
+            = "\\" character:(
+                "n" { '\n' } / "r" { '\r' } / "t" { '\t' } / "0" { '\0' }
+                / "\\" { '\\' } / "\"" { '"' } / "'" { '\'' } / "/" { '/' }
+                / "u{" digits:$(['0'..='9' | 'a'..='f' | 'A'..='F']*<1,6>) "}" {?
+                    u32::from_str_radix(digits, 16)
+                        .ok()
+                        .and_then(std::char::from_u32)
+                        .ok_or("a valid unicode code point")
+                }
+            ) { character }
+
+        rule character() -> Ast
+            = "'" character:(escape() / !['\'' | '\\' | '\n'] character:any_character() { character }) "'" {
+                Ast::CharLiteral(character)
+             }
+
+        rule any_character() -> char
+            = character:$([_]) { character.chars().next().unwrap() }
+
+        // Triple quoted strings can span lines and contain lone quotes
+        rule multiline_string() -> Ast
+            = "\"\"\"" characters:(escape() / !("\"\"\"" / "\\") character:any_character() { character })* "\"\"\"" {
+                Ast::StringLiteral(characters.into_iter().collect())
+             }
 
         rule string() -> Ast
-            = "\"" string:$((!['"' | '\\'] [_] / escape())*) "\"" { Ast::StringLiteral(string.to_string()) }
+            = multiline_string()
+            / "\"" characters:(escape() / !['"' | '\\'] character:any_character() { character })* "\"" {
+                Ast::StringLiteral(characters.into_iter().collect())
+             }
+
+        // Names things get bound to. `_` binds something without giving it a name
+        rule binder() -> String
+            = variable_name()
+            / "_" !(alphanumeric() / "'") { "_".to_string() }
 
         rule assignment() -> (String, Vec<String>, Ast)
-            = name:variable_name() whitespace()* params:(variable_name() ** (whitespace()*)) whitespace()* "=" whitespace()* value:expression() { (name, params, value) }
+            = name:binder() whitespace()* params:(binder() ** (whitespace()*)) whitespace()* "=" whitespace()* value:expression() { (name, params, value) }
 
         rule lambda() -> Ast
-            = "\\" whitespace()* args:(variable_name() ** (whitespace()*)) whitespace()* "->" whitespace()* body:expression() {
+            = "\\" whitespace()* args:(binder() ** (whitespace()*)) whitespace()* "->" whitespace()* body:expression() {
                 Ast::lambda_chain(body, args)
              }
 
@@ -122,36 +555,138 @@ peg::parser! {
                 Ast::new_let(value.0, Ast::lambda_chain(value.2, value.1), body)
              }
 
+        rule foreign_expr() -> Ast
+            = "foreign" __ "import" __ name:variable_name() _ ty:annotation() "in" __ body:expression() {
+                Ast::new_foreign(name, ty, body)
+             }
+
+        // Parenthesis let literals like #rgb(1, 2, 3) contain spaces
+        rule custom_literal() -> Ast
+            = "#" text:$(("(" (!")" [_])* ")" / !([' ' | '\n' | '\t' | '(' | ')' | ';' | '}']) [_])+) {
+                Ast::CustomLiteral(text.to_string())
+             }
+
+        rule hole() -> Ast
+            = offset:position!() "?" name:variable_name() { Ast::Hole(offset, Some(name)) }
+            / offset:position!() "_" !(alphanumeric() / "'") { Ast::Hole(offset, None) }
+
+        // Statements have to end where the next one starts, so a statement with junk after
+        // it gets recovered as a whole instead of failing the entire block
+        rule statement() -> Statement
+            = name:binder() _ "<-" _ action:expression() &(";" / "}") { Statement::Bind(name, action) }
+            / action:expression() &(";" / "}") { Statement::Run(action) }
+            / start:position!() text:$((!(";" / "}") [_])+) end:position!() {?
+                recovered(errors, start, end, text).map(Statement::Run)
+            }
+
+        rule do_expr() -> Ast
+            = "do" _ "{" _ statements:(statement() ++ (";" _)) "}" {?
+                let mut statements = statements;
+
+                match statements.pop() {
+                    Some(Statement::Run(result)) => Ok(Ast::Do(statements, Box::new(result))),
+                    _ => Err("a do block ending in an expression"),
+                }
+             }
+
+        // The last branch has to be otherwise, so there is always something to fall back on
+        rule multi_way_if() -> Ast
+            = "if" _ branches:multi_way_branch()+ "|" _ "otherwise" _ "->" _ otherwise:expression() {
+                Ast::MultiWayIf(branches, Box::new(otherwise))
+             }
+            / "if" _ multi_way_branch()+ {? Err("an otherwise branch ending the multi-way if") }
+
+        rule multi_way_branch() -> (Ast, Ast)
+            = "|" _ !("otherwise" _ "->") condition:expression() "->" _ value:expression() { (condition, value) }
+
         rule if_expr() -> Ast
             = "if" whitespace()+ condition:expression() "then" whitespace()+ left:expression() "else" whitespace()+ right:expression() { Ast::new_if(condition, left, right) }
 
         rule wrapped() -> Ast
-            = "(" ret:expression() ")" { ret }
+            = "(" _ ret:expression() ")" { ret }
+            / "(" start:position!() text:$(balanced()*) end:position!() ")" {?
+                recovered(errors, start, end, text)
+            }
+
+        rule balanced() = "(" enter() balanced()* leave() ")" / !['(' | ')'] [_]
 
         rule atom() -> Ast
-            = ret:(if_expr() / let_expr() / lambda() / number() / string() / identifier() / wrapped()) whitespace()* { ret }
+            = start:position!() ret:(multi_way_if() / if_expr() / let_expr() / foreign_expr() / do_expr() / lambda() / number() / negation() / string() / character() / custom_literal() / hole() / identifier() / wrapped()) end:position!() whitespace()* {
+                ret.locate(start, end)
+             }
 
         rule unannotated() -> Ast
-            = function:atom() args:(atom() ** (whitespace()*)) whitespace()* { Ast::call_chain(function, args) }
+            = negated_call() / call()
+
+        rule call() -> Ast
+            = start:position!() function:atom() args:(atom() ** (whitespace()*)) end:position!() whitespace()* {
+                if args.is_empty() {
+                    function
+                } else {
+                    Ast::call_chain(function, args).locate(start, end)
+                }
+             }
+
+        // f >> g and g << f both run f first. Mixing them chains to the left as well
+        rule composed() -> Ast
+            = start:position!() first:unannotated() rest:(operator:composition() _ function:unannotated() end:position!() { (operator, function, end) })* {
+                rest.into_iter().fold(first, |left, (operator, right, end)| {
+                    Ast::new_operator(operator, left, right).locate(start, end)
+                })
+             }
+
+        rule composition() -> Operator
+            = ">>" { Operator::ComposeForward }
+            / "<<" { Operator::ComposeBackward }
+
+        // Chaining comparisons wouldn't mean anything useful, so there's at most one per expression
+        rule compared() -> Ast
+            = start:position!() left:composed() right:(operator:comparison() _ right:composed() end:position!() { (operator, right, end) })? {
+                match right {
+                    Some((operator, right, end)) => {
+                        Ast::new_operator(operator, left, right).locate(start, end)
+                    }
+                    None => left,
+                }
+             }
+
+        rule comparison() -> Operator
+            = "==" { Operator::Equal }
+            / "/=" { Operator::NotEqual }
+
+        // x |> f |> g is the same as g (f x). Every stage gets the span from x to its function
+        rule piped() -> Ast
+            = start:position!() first:compared() stages:("|>" _ stage:compared() end:position!() { (stage, end) })* {
+                stages.into_iter().fold(first, |argument, (function, end)| {
+                    Ast::new_operator(Operator::Pipe, argument, function).locate(start, end)
+                })
+             }
 
         // Type level syntax
         rule t_identifier() -> Type
             = name:variable_name() {
                 let first_char = &name[0..1];
 
-                if first_char == first_char.to_uppercase() {
+                if name == "*" {
+                    Type::star()
+                } else if first_char == first_char.to_uppercase() {
                     Type::constant(&name[..])
                 } else {
-                    Type::Variable(VarName { name, kind: Box::new(Type::NoKind) })
+                    Type::Variable(VarName { name, kind: Rc::new(Type::NoKind) })
                 }
              }
 
+        rule t_wildcard() -> Type
+            = offset:position!() "_" { Type::wildcard(offset) }
+
         rule t_small() -> Type
-            = ret:(t_wrapped() / t_identifier()) whitespace()* { ret }
+            = ret:(t_wrapped() / t_wildcard() / t_identifier()) whitespace()* { ret }
 
         rule t_wrapped() -> Type
             = "(" whitespace()* ret:t_atom() ")" { ret }
 
+        // Lambdas start with the same types, so cache them to not try each one twice per nesting level
+        #[cache]
         rule t_non_lambda() -> Type
             = fun:t_small() args:(t_small()*) { fun.app_chain(args) }
 
@@ -159,28 +694,338 @@ peg::parser! {
             = from:t_non_lambda() "->" whitespace()* to:t_atom() { Type::create_lambda(from, to) }
 
         rule t_bounded_var() -> VarName
-            = "(" _ name:variable_name() _ "::" _  ty:t_atom() _ ")" _ { VarName { kind: Box::new(ty), name } }
+            = "(" _ name:variable_name() _ "::" _  ty:t_atom() _ ")" _ { VarName { kind: Rc::new(ty), name } }
 
         rule t_unbounded_var() -> VarName
-            = name:variable_name() _ { VarName { name, kind: Box::new(Type::NoKind) } }
+            = name:variable_name() _ { VarName { name, kind: Rc::new(Type::NoKind) } }
 
         rule t_forall_var() -> VarName
             = t_bounded_var() / t_unbounded_var()
 
         rule t_forall() -> Type
-            = "forall" __ variables:(t_forall_var()+) _ "." _ ty:t_atom() { Type::Scheme { variables, ty: Box::new(ty) } }
+            = "forall" __ variables:(t_forall_var()+) _ "." _ ty:t_atom() { Type::Scheme { variables, ty: Rc::new(ty) } }
 
         rule t_atom() -> Type
-            = ret:(t_forall() / t_lambda() / t_non_lambda()) _ { ret }
+            = enter() ret:(t_forall() / t_lambda() / t_non_lambda())? leave() ret:quiet!{ {? ret.ok_or("") } } _ { ret }
 
         rule annotation() -> Type
             = "::" whitespace()* ret:t_atom() { ret }
 
-        pub rule expression() -> Ast
-             = expression:unannotated() annotations:annotation()* { expression.annotate_many(annotations) }
+        // Pipes and calls with lots of arguments nest the tree without nesting the parser,
+        // so the whole tree gets checked again. Recovering parses do that themselves, since
+        // they can point at the whole program
+        pub rule program() -> Ast
+            = _ ret:expression() rest:(start:position!() text:$([_]+) end:position!() {?
+                recovered(errors, start, end, text)
+            })? {?
+                if errors.is_some() || ret.depth() <= MAX_NESTING {
+                    Ok(ret)
+                } else {
+                    ret.discard();
+                    Err(NESTED_TOO_DEEPLY)
+                }
+             }
+
+        rule expression() -> Ast
+            = enter() expression:unlimited_expression()? leave() expression:quiet!{ {? expression.ok_or("") } } {
+                expression
+             }
+
+        rule unlimited_expression() -> Ast
+             = start:position!() expression:piped() annotations:annotation()* end:position!() {
+                if annotations.is_empty() {
+                    expression
+                } else {
+                    expression.annotate_many(annotations).locate(start, end)
+                }
+              }
+    }
+}
+
+pub fn parse_expression(value: &str) -> Result<Ast, peg::error::ParseError<peg::str::LineCol>> {
+    let comments = RefCell::new(BTreeMap::new());
+    let ast = parse::program(value, &comments, None, &Cell::new(0))?;
+
+    Ok(with_comments(ast, value, comments))
+}
+
+// Parse as much as possible. Whatever can't be parsed becomes an Ast::Error and a syntax error.
+// The tree is only missing when not even the start of an expression could be found, or when
+// it's nested too deeply for anything to be done with it
+pub fn parse_expression_recovering(value: &str) -> (Option<Ast>, Vec<SyntaxError>) {
+    let comments = RefCell::new(BTreeMap::new());
+    let errors = RefCell::new(BTreeMap::new());
+
+    match parse::program(value, &comments, Some(&errors), &Cell::new(0)) {
+        Ok(ast) if ast.depth() > MAX_NESTING => {
+            ast.discard();
+            let error = SyntaxError {
+                span: Span::new(0, value.len()),
+                message: format!("expected {}", NESTED_TOO_DEEPLY),
+            };
+
+            (None, vec![error])
+        }
+        Ok(ast) => (
+            Some(with_comments(ast, value, comments)),
+            errors.into_inner().into_values().collect(),
+        ),
+        Err(err) => {
+            let offset = err.location.offset;
+            let error = SyntaxError {
+                span: Span::new(offset, value.len()),
+                message: format!("expected {}", err.expected),
+            };
+
+            (None, vec![error])
+        }
+    }
+}
+
+fn with_comments(ast: Ast, value: &str, comments: RefCell<BTreeMap<usize, Comment>>) -> Ast {
+    let comments: Vec<_> = comments.into_inner().into_values().collect();
+    let mut comments = comments.into_iter().peekable();
+
+    let ast = attach_comments(ast, value, &mut comments);
+
+    // Whatever is left comes after the whole expression
+    let trailing: Vec<_> = comments.collect();
+    if trailing.is_empty() {
+        ast
+    } else {
+        with_trivia(ast, Vec::new(), trailing)
+    }
+}
+
+// Every comment in a program, in source order
+pub fn parse_comments(
+    value: &str,
+) -> Result<Vec<Comment>, peg::error::ParseError<peg::str::LineCol>> {
+    let comments = RefCell::new(BTreeMap::new());
+    parse::program(value, &comments, None, &Cell::new(0))?;
+
+    Ok(comments.into_inner().into_values().collect())
+}
+
+type Comments = std::iter::Peekable<std::vec::IntoIter<Comment>>;
+
+fn with_trivia(ast: Ast, leading: Vec<Comment>, trailing: Vec<Comment>) -> Ast {
+    match ast {
+        Ast::Commented(mut trivia, ast) => {
+            trivia.leading.splice(0..0, leading);
+            trivia.trailing.extend(trailing);
+            Ast::Commented(trivia, ast)
+        }
+        ast => Ast::Commented(Trivia { leading, trailing }, Box::new(ast)),
+    }
+}
+
+// Walk the tree in source order, giving each comment to the located node it's closest to.
+// Comments before a node lead it, comments right after it on the same line trail it
+fn attach_comments(ast: Ast, source: &str, comments: &mut Comments) -> Ast {
+    let span = match &ast {
+        Ast::Located(span, _) => Some(*span),
+        _ => None,
+    };
+
+    let leading = match span {
+        Some(span) => take_while(comments, |comment| comment.span.end <= span.start),
+        None => Vec::new(),
+    };
+
+    let ast = ast.map_children(&mut |child| attach_comments(child, source, comments));
+
+    let trailing = match span {
+        Some(span) => {
+            let mut end = span.end;
+
+            take_while(comments, |comment| {
+                let directly_after = comment.span.start >= end
+                    && source[end..comment.span.start]
+                        .chars()
+                        .all(|character| character == ' ' || character == '\t');
+
+                if directly_after {
+                    end = comment.span.end;
+                }
+
+                directly_after
+            })
+        }
+        None => Vec::new(),
+    };
+
+    if leading.is_empty() && trailing.is_empty() {
+        ast
+    } else {
+        with_trivia(ast, leading, trailing)
     }
 }
 
-pub fn parse_expression(value: &String) -> Result<Ast, peg::error::ParseError<peg::str::LineCol>> {
-    parse::expression(value)
+fn take_while(
+    comments: &mut Comments,
+    mut predicate: impl FnMut(&Comment) -> bool,
+) -> Vec<Comment> {
+    let mut taken = Vec::new();
+
+    while let Some(comment) = comments.peek() {
+        if predicate(comment) {
+            taken.push(comments.next().unwrap());
+        } else {
+            break;
+        }
+    }
+
+    taken
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump;
+
+    // Every level used to get parsed twice when no comparison followed it, which took
+    // seconds at 20 levels
+    #[test]
+    fn deeply_nested_lets_parse_quickly() {
+        let source = format!("{}x", "let x = 1 in ".repeat(100));
+
+        assert!(parse_expression(&source).is_ok());
+    }
+
+    // Nested deeper than MAX_NESTING, each in a different way
+    fn too_deep() -> Vec<String> {
+        vec![
+            format!("{}1{}", "(".repeat(3000), ")".repeat(3000)),
+            format!("{}x", "let x = 1 in ".repeat(5000)),
+            format!("1{}", " |> negate".repeat(5000)),
+            format!("f{}", " 1".repeat(5000)),
+            format!("1 :: {}Number{}", "(".repeat(3000), ")".repeat(3000)),
+        ]
+    }
+
+    // Test threads get a lot less stack than the cli, so these make their own
+    #[test]
+    fn deep_nesting_is_a_syntax_error() {
+        with_stack(|| {
+            for source in too_deep() {
+                assert!(parse_expression(&source).is_err());
+
+                let (ast, errors) = parse_expression_recovering(&source);
+                assert!(ast.map(|ast| ast.depth()).unwrap_or(0) <= MAX_NESTING);
+                assert!(!errors.is_empty());
+            }
+        });
+    }
+
+    #[test]
+    fn nesting_up_to_the_limit_parses() {
+        with_stack(|| {
+            let source = format!("{}1{}", "(".repeat(490), ")".repeat(490));
+
+            assert!(parse_expression(&source).is_ok());
+        });
+    }
+
+    // The negation of a call covers all of its arguments
+    #[test]
+    fn negation_takes_in_the_whole_call() {
+        let dumped = |source: &str| dump::ast(&parse_expression(source).unwrap());
+
+        assert_eq!(dumped("-add 1 2"), dumped("-(add 1 2)"));
+        assert_eq!(dumped("f -x y"), dumped("f (-x) y"));
+        assert_eq!(dumped("-1"), "(float -1)");
+    }
+
+    #[test]
+    fn characters_parse_with_escapes() {
+        let dumped = |source: &str| dump::ast(&parse_expression(source).unwrap());
+
+        assert_eq!(dumped("'a'"), "(char 'a')");
+        assert_eq!(dumped("'\\n'"), "(char '\\n')");
+        assert_eq!(dumped("'\\''"), "(char '\\'')");
+        assert_eq!(dumped("'\\u{1F600}'"), "(char '\u{1F600}')");
+        assert!(parse_expression("'ab'").is_err());
+    }
+
+    // Quotes can't start a name anymore, but they can still end one
+    #[test]
+    fn quotes_stay_allowed_inside_names() {
+        assert_eq!(dump::ast(&parse_expression("x'").unwrap()), "(var x')");
+    }
+
+    #[test]
+    fn strings_decode_their_escapes() {
+        let dumped = |source: &str| dump::ast(&parse_expression(source).unwrap());
+
+        assert_eq!(dumped("\"a\\tb\\\"c\""), "(string \"a\\tb\\\"c\")");
+        assert_eq!(dumped("\"\\u{41}\""), "(string \"A\")");
+        assert!(parse_expression("\"\\q\"").is_err());
+    }
+
+    #[test]
+    fn triple_quoted_strings_span_lines() {
+        let dumped = |source: &str| dump::ast(&parse_expression(source).unwrap());
+
+        assert_eq!(
+            dumped("\"\"\"a \"b\"\nc\\t\"\"\""),
+            "(string \"a \\\"b\\\"\\nc\\t\")"
+        );
+    }
+
+    // The pieces which couldn't be parsed, and the tree built around them
+    fn recovered(source: &str) -> (Option<String>, Vec<(usize, usize)>) {
+        let (ast, errors) = parse_expression_recovering(source);
+        let spans = errors
+            .iter()
+            .map(|error| (error.span.start, error.span.end))
+            .collect();
+
+        (ast.map(|ast| dump::ast(&ast)), spans)
+    }
+
+    #[test]
+    fn syntax_errors_get_recovered_from() {
+        let (ast, spans) = recovered("f (a $) (b $)");
+        assert_eq!(
+            ast.unwrap(),
+            "(call\n  (call\n    (var f)\n    (error \"a $\"))\n  (error \"b $\"))"
+        );
+        assert_eq!(spans, vec![(3, 6), (9, 12)]);
+
+        let (ast, spans) = recovered("do { x <- 1 $ 2; print x }");
+        assert!(ast.unwrap().contains("(error \"x <- 1 $ 2\")"));
+        assert_eq!(spans, vec![(5, 15)]);
+
+        let (ast, spans) = recovered("1 2 )");
+        assert_eq!(ast.unwrap(), "(call\n  (float 1)\n  (float 2))");
+        assert_eq!(spans, vec![(4, 5)]);
+    }
+
+    // Without even the start of an expression there is nothing to build a tree from
+    #[test]
+    fn recovering_needs_the_start_of_an_expression() {
+        let (ast, spans) = recovered(")");
+
+        assert!(ast.is_none());
+        assert_eq!(spans, vec![(0, 1)]);
+        assert!(parse_expression("f (a $)").is_err());
+    }
+
+    #[test]
+    fn only_things_which_cant_do_anything_are_values() {
+        let is_value = |source: &str| parse_expression(source).unwrap().is_value();
+
+        assert!(is_value("\\x -> x"));
+        assert!(is_value("(1 :: Number)"));
+        assert!(is_value("let f = \\x -> x in f"));
+        assert!(!is_value("f 1"));
+        assert!(!is_value("let x = f 1 in \\y -> y"));
+        assert!(!is_value("x |> f"));
+    }
+
+    #[test]
+    fn nesting_message_mentions_the_limit() {
+        assert!(NESTED_TOO_DEEPLY.contains(&MAX_NESTING.to_string()));
+    }
 }
@@ -0,0 +1,185 @@
+use crate::diagnostic::Diagnostic;
+use crate::parser::Ast;
+use crate::type_checker::typed_ast::TypedAst;
+
+// Looks at a program which type checked and points out things worth changing
+pub trait Lint {
+    fn name(self: &Self) -> &str;
+    fn check(self: &Self, program: &TypedAst) -> Vec<Diagnostic>;
+}
+
+// Rewrites a program before it gets type checked, like the desugaring we do ourselves
+pub trait Pass {
+    fn name(self: &Self) -> &str;
+    fn run(self: &Self, program: Ast) -> Ast;
+}
+
+// Something the REPL runs when a line starts with `:name`
+pub trait Command {
+    fn name(self: &Self) -> &str;
+    // Gets whatever comes after the name on the line
+    fn run(self: &Self, argument: &str) -> Result<String, String>;
+}
+
+// Everything registered by code embedding the compiler
+#[derive(Default)]
+pub struct Plugins {
+    lints: Vec<Box<dyn Lint>>,
+    passes: Vec<Box<dyn Pass>>,
+    commands: Vec<Box<dyn Command>>,
+}
+
+impl Plugins {
+    pub fn with_lint(mut self: Plugins, lint: impl Lint + 'static) -> Plugins {
+        self.lints.push(Box::new(lint));
+        self
+    }
+
+    // Passes run in the order they were added
+    pub fn with_pass(mut self: Plugins, pass: impl Pass + 'static) -> Plugins {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn with_command(mut self: Plugins, command: impl Command + 'static) -> Plugins {
+        self.commands.push(Box::new(command));
+        self
+    }
+
+    pub fn has_lints(self: &Plugins) -> bool {
+        !self.lints.is_empty()
+    }
+
+    pub fn run_passes(self: &Plugins, program: Ast) -> Ast {
+        self.passes
+            .iter()
+            .fold(program, |program, pass| pass.run(program))
+    }
+
+    pub fn run_lints(self: &Plugins, program: &TypedAst) -> Vec<Diagnostic> {
+        self.lints
+            .iter()
+            .flat_map(|lint| lint.check(program))
+            .collect()
+    }
+
+    // Run a `:name argument` line. None when the line isn't a command at all
+    pub fn run_command(self: &Plugins, line: &str) -> Option<Result<String, String>> {
+        let line = line.trim().strip_prefix(':')?;
+        let (name, argument) = match line.find(char::is_whitespace) {
+            Some(index) => (&line[..index], line[index..].trim()),
+            None => (line, ""),
+        };
+
+        Some(
+            match self.commands.iter().find(|command| command.name() == name) {
+                Some(command) => command.run(argument),
+                None => Err(format!("Unknown command :{}", name)),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::{check_source, check_source_with, Severity};
+    use crate::messages::Locale;
+    use crate::type_checker::type_::TypeContext;
+
+    // Binds answer around the whole program, so it's in scope while inferring
+    struct Answer;
+
+    impl Pass for Answer {
+        fn name(self: &Self) -> &str {
+            "answer"
+        }
+
+        fn run(self: &Self, program: Ast) -> Ast {
+            Ast::Let(
+                "answer".to_string(),
+                Box::new(Ast::FloatLiteral(42.0)),
+                Box::new(program),
+            )
+        }
+    }
+
+    // Points out the type the whole program ended up with
+    struct ProgramType;
+
+    impl Lint for ProgramType {
+        fn name(self: &Self) -> &str {
+            "program-type"
+        }
+
+        fn check(self: &Self, program: &TypedAst) -> Vec<Diagnostic> {
+            vec![Diagnostic {
+                severity: Severity::Note,
+                code: None,
+                message: format!("{}", program.ty),
+                span: None,
+                notes: vec![],
+            }]
+        }
+    }
+
+    struct Echo;
+
+    impl Command for Echo {
+        fn name(self: &Self) -> &str {
+            "echo"
+        }
+
+        fn run(self: &Self, argument: &str) -> Result<String, String> {
+            Ok(argument.to_string())
+        }
+    }
+
+    #[test]
+    fn passes_run_before_inference() {
+        let check = |plugins: &Plugins| {
+            check_source_with("add answer 1", TypeContext::new(), Locale::English, plugins)
+                .0
+                .map(|ty| ty.to_string())
+        };
+
+        assert_eq!(check(&Plugins::default()), None);
+        assert_eq!(
+            check(&Plugins::default().with_pass(Answer)),
+            Some("Number".to_string())
+        );
+    }
+
+    #[test]
+    fn lints_see_inferred_types() {
+        let plugins = Plugins::default().with_lint(ProgramType);
+        let (_, diagnostics) = check_source_with(
+            "\\x -> add x 1",
+            TypeContext::new(),
+            Locale::English,
+            &plugins,
+        );
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+
+        assert_eq!(messages, vec!["Number -> Number"]);
+
+        // Without the lint nothing gets said about a program which type checks
+        let (_, diagnostics) = check_source("\\x -> add x 1", TypeContext::new(), Locale::English);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn commands_get_the_rest_of_the_line() {
+        let plugins = Plugins::default().with_command(Echo);
+
+        assert_eq!(
+            plugins.run_command(":echo  hi there "),
+            Some(Ok("hi there".to_string()))
+        );
+        assert_eq!(
+            plugins.run_command(":nope"),
+            Some(Err("Unknown command :nope".to_string()))
+        );
+        assert_eq!(plugins.run_command("echo hi"), None);
+    }
+}
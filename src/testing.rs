@@ -0,0 +1,403 @@
+// Random programs and types for checking properties which should always hold, like
+// formatting then parsing giving back the same program. Exported so embedders
+// extending the language can check their own changes the same way
+use crate::bench::many_constraints;
+use crate::dump;
+use crate::format::{format_expression, DEFAULT_WIDTH};
+use crate::parser::{parse_expression, Ast, Operator, Statement};
+use crate::type_checker::type_::{
+    get_type_of, get_typed_ast, merge_substitutions, simplify_constraints, ConstraintOrigin,
+    Reason, Substituable, Substitution, Type, TypeConstraint, TypeContext, VarName,
+};
+use crate::type_checker::typed_ast::TypedNode;
+use std::rc::Rc;
+
+// Names which can't clash with keywords or the ones holes use
+const VARIABLES: &[&str] = &["x", "y", "f", "value", "add", "x'"];
+const TYPE_VARIABLES: &[&str] = &["a", "b", "c"];
+const CONSTRUCTORS: &[&str] = &["Number", "String", "Boolean", "Char"];
+const TYPE_FUNCTIONS: &[&str] = &["List", "Effect", "Maybe"];
+const OPERATORS: &[Operator] = &[
+    Operator::Pipe,
+    Operator::ComposeForward,
+    Operator::ComposeBackward,
+    Operator::Equal,
+    Operator::NotEqual,
+];
+
+// Adding up 2^11 numbers gives the solver about 8000 constraints to go through at once
+const MANY_CONSTRAINTS_DEPTH: usize = 11;
+
+// Characters worth putting in strings, since each of them takes some escaping or care
+const CHARACTERS: &[char] = &['a', 'Z', ' ', '"', '\'', '\\', '\n', '\t', 'ă', '-', '{'];
+
+// A small xorshift generator, so the same seed always gives the same values
+#[derive(Debug, Clone)]
+pub struct Gen {
+    state: u64,
+}
+
+impl Gen {
+    pub fn new(seed: u64) -> Gen {
+        // Xorshift gets stuck on 0
+        Gen {
+            state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+        }
+    }
+
+    pub fn next_u64(self: &mut Gen) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    // A number from 0 up to, but not including, the given one
+    pub fn below(self: &mut Gen, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    pub fn pick<T: Copy>(self: &mut Gen, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+// A type with nesting up to some depth, only using syntax annotations can contain
+pub fn arbitrary_type(gen: &mut Gen, depth: usize) -> Type {
+    let choice = if depth == 0 {
+        gen.below(2)
+    } else {
+        gen.below(5)
+    };
+
+    match choice {
+        0 => Type::constant(gen.pick(CONSTRUCTORS)),
+        1 => Type::Variable(VarName {
+            name: gen.pick(TYPE_VARIABLES).to_string(),
+            kind: Rc::new(Type::NoKind),
+        }),
+        2 => Type::constant(gen.pick(TYPE_FUNCTIONS)).apply(arbitrary_type(gen, depth - 1)),
+        3 => Type::create_lambda(
+            arbitrary_type(gen, depth - 1),
+            arbitrary_type(gen, depth - 1),
+        ),
+        _ => {
+            let ty = arbitrary_type(gen, depth - 1);
+            let variables: Vec<_> = ty.free_variables().into_iter().collect();
+
+            if variables.is_empty() || ty.is_scheme() {
+                ty
+            } else {
+                ty.to_scheme(variables)
+            }
+        }
+    }
+}
+
+fn arbitrary_string(gen: &mut Gen) -> String {
+    (0..gen.below(6)).map(|_| gen.pick(CHARACTERS)).collect()
+}
+
+// An expression with nesting up to some depth. Only made of things the parser can produce,
+// so no spans, comments or parse errors
+pub fn arbitrary_ast(gen: &mut Gen, depth: usize) -> Ast {
+    let choice = if depth == 0 {
+        gen.below(5)
+    } else {
+        gen.below(15)
+    };
+    let variable = |gen: &mut Gen| gen.pick(VARIABLES).to_string();
+    // `_` can bind things, but referring to it makes a hole
+    let binder = |gen: &mut Gen| {
+        if gen.below(8) == 0 {
+            "_".to_string()
+        } else {
+            variable(gen)
+        }
+    };
+
+    match choice {
+        0 => Ast::Variable(variable(gen)),
+        1 => Ast::FloatLiteral(gen.below(2000) as f64 - 1000.0),
+        2 => Ast::StringLiteral(arbitrary_string(gen)),
+        3 => Ast::CharLiteral(gen.pick(CHARACTERS)),
+        4 => Ast::Hole(0, Some(variable(gen))),
+        5 => Ast::new_if(
+            arbitrary_ast(gen, depth - 1),
+            arbitrary_ast(gen, depth - 1),
+            arbitrary_ast(gen, depth - 1),
+        ),
+        6 => Ast::new_let(
+            binder(gen),
+            arbitrary_ast(gen, depth - 1),
+            arbitrary_ast(gen, depth - 1),
+        ),
+        7 | 8 => Ast::new_call(arbitrary_ast(gen, depth - 1), arbitrary_ast(gen, depth - 1)),
+        9 => Ast::new_lambda(binder(gen), arbitrary_ast(gen, depth - 1)),
+        10 => Ast::Annotation(
+            Box::new(arbitrary_ast(gen, depth - 1)),
+            arbitrary_type(gen, depth - 1),
+        ),
+        11 => Ast::new_operator(
+            gen.pick(OPERATORS),
+            arbitrary_ast(gen, depth - 1),
+            arbitrary_ast(gen, depth - 1),
+        ),
+        12 => Ast::Negation(Box::new(arbitrary_ast(gen, depth - 1))),
+        13 => {
+            let statements = (0..gen.below(3))
+                .map(|_| {
+                    if gen.below(2) == 0 {
+                        Statement::Bind(binder(gen), arbitrary_ast(gen, depth - 1))
+                    } else {
+                        Statement::Run(arbitrary_ast(gen, depth - 1))
+                    }
+                })
+                .collect();
+
+            Ast::Do(statements, Box::new(arbitrary_ast(gen, depth - 1)))
+        }
+        _ => {
+            let branches = (0..gen.below(2) + 1)
+                .map(|_| (arbitrary_ast(gen, depth - 1), arbitrary_ast(gen, depth - 1)))
+                .collect();
+
+            Ast::MultiWayIf(branches, Box::new(arbitrary_ast(gen, depth - 1)))
+        }
+    }
+}
+
+// A substitution for some of the type variables the generators use
+pub fn arbitrary_substitution(gen: &mut Gen, depth: usize) -> Substitution {
+    let mut substitution = Substitution::new();
+
+    for name in TYPE_VARIABLES {
+        if gen.below(2) == 0 {
+            substitution.insert(name.to_string(), arbitrary_type(gen, depth));
+        }
+    }
+
+    substitution
+}
+
+// Formatting an expression and parsing the result has to give back the same expression,
+// which formats to the same source again
+pub fn check_round_trip(expression: &Ast) -> Result<(), String> {
+    let formatted = format_expression(expression, DEFAULT_WIDTH);
+    let parsed = parse_expression(&formatted)
+        .map_err(|err| format!("Formatted program doesn't parse: {}\n{}", err, formatted))?;
+
+    let expected = dump::ast(expression);
+    let found = dump::ast(&parsed);
+
+    if expected != found {
+        return Err(format!(
+            "Formatting changed the program:\n{}\nwas parsed as\n{}\ninstead of\n{}",
+            formatted, found, expected
+        ));
+    }
+
+    let reformatted = format_expression(&parsed, DEFAULT_WIDTH);
+
+    if reformatted == formatted {
+        Ok(())
+    } else {
+        Err(format!(
+            "Formatting twice changed the source:\n{}\nbecame\n{}",
+            formatted, reformatted
+        ))
+    }
+}
+
+// Applying nothing changes nothing, and applying two substitutions one after the other
+// is the same as applying the result of merging them
+pub fn check_substitution_laws(
+    ty: &Type,
+    first: &Substitution,
+    second: &Substitution,
+) -> Result<(), String> {
+    if ty.clone().apply_substitution(&Substitution::new()) != *ty {
+        return Err(format!("The empty substitution changed {}", ty));
+    }
+
+    let one_by_one = ty
+        .clone()
+        .apply_substitution(first)
+        .apply_substitution(second);
+    let merged = ty
+        .clone()
+        .apply_substitution(&merge_substitutions(second.clone(), first.clone()));
+
+    if one_by_one == merged {
+        Ok(())
+    } else {
+        Err(format!(
+            "Substituting {} one by one gave {}, but the merged substitution gave {}",
+            ty, one_by_one, merged
+        ))
+    }
+}
+
+// Putting an unrelated let in front of a program takes up fresh variables before any of
+// the program's. None are left once it's solved, so nothing in the program may get renamed
+pub fn check_stable_names(expression: &Ast) -> Result<(), String> {
+    let value = Ast::Variable("trace".to_string()).call_chain(vec![
+        Ast::StringLiteral(String::new()),
+        Ast::FloatLiteral(1.0),
+    ]);
+    let wrapped = Ast::new_let("unrelated".to_string(), value, expression.clone());
+
+    let (typed, wrapped) = match (get_typed_ast(expression.clone()), get_typed_ast(wrapped)) {
+        (Ok(typed), Ok(wrapped)) => (typed, wrapped),
+        _ => return Ok(()),
+    };
+
+    match wrapped.node {
+        TypedNode::Let(_, _, body) if dump::typed_ast(&body) == dump::typed_ast(&typed) => Ok(()),
+        TypedNode::Let(_, _, body) => Err(format!(
+            "Putting a let in front of {} renamed its variables from\n{}\nto\n{}",
+            format_expression(expression, DEFAULT_WIDTH),
+            dump::typed_ast(&typed),
+            dump::typed_ast(&body)
+        )),
+        _ => Err("Type checking a let didn't give back a let".to_string()),
+    }
+}
+
+// Checking a program and rolling back to before it leaves nothing of it behind, whether
+// it type checked or not
+pub fn check_rollback(expression: &Ast) -> Result<(), String> {
+    let mut context = TypeContext::new().with_error_recovery();
+    let before = format!("{:?}", context);
+
+    let snapshot = context.snapshot();
+    if context.infer(expression.clone()).is_ok() {
+        let _ = context.solve_constraints();
+    }
+    context.rollback(snapshot);
+
+    if format!("{:?}", context) == before {
+        Ok(())
+    } else {
+        Err(format!(
+            "Rolling back after checking {} didn't restore the context",
+            format_expression(expression, DEFAULT_WIDTH)
+        ))
+    }
+}
+
+// Simplifying keeps one unification out of left ~ right and right ~ left, drops left ~ left,
+// and leaves matching alone, since which way that goes matters
+pub fn check_simplification(left: &Type, right: &Type) -> Result<(), String> {
+    if left == right {
+        return Ok(());
+    }
+
+    let origin = ConstraintOrigin {
+        span: None,
+        reason: Reason::Application,
+    };
+    let simplified = simplify_constraints(vec![
+        TypeConstraint::Unify(left.clone(), right.clone(), origin),
+        TypeConstraint::Unify(right.clone(), left.clone(), origin),
+        TypeConstraint::Unify(left.clone(), left.clone(), origin),
+        TypeConstraint::Match(left.clone(), right.clone(), origin),
+        TypeConstraint::Match(right.clone(), left.clone(), origin),
+    ]);
+
+    if simplified.len() == 3 {
+        Ok(())
+    } else {
+        let constraints: Vec<_> = simplified.iter().map(ToString::to_string).collect();
+        Err(format!(
+            "Simplifying the constraints between {} and {} left {}",
+            left,
+            right,
+            constraints.join(", ")
+        ))
+    }
+}
+
+// Solving thousands of constraints has to work without running out of stack
+pub fn check_many_constraints(depth: usize) -> Result<(), String> {
+    match get_type_of(many_constraints(depth)) {
+        // The type comes back as a scheme, even without any variables to quantify
+        Ok(ty) if ty.to_string() == Type::number().to_string() => Ok(()),
+        Ok(ty) => Err(format!("Adding up 2^{} numbers gave {}", depth, ty)),
+        Err(error) => Err(format!("Adding up 2^{} numbers failed: {}", depth, error)),
+    }
+}
+
+// Check the properties on as many random inputs as asked for, and that big programs
+// still solve, collecting what failed
+pub fn check_properties(seed: u64, count: usize, depth: usize) -> Vec<String> {
+    let mut gen = Gen::new(seed);
+    let mut failures = Vec::new();
+
+    for _ in 0..count {
+        let expression = arbitrary_ast(&mut gen, depth);
+        if let Err(message) = check_round_trip(&expression) {
+            failures.push(message);
+        }
+
+        if let Err(message) = check_stable_names(&expression) {
+            failures.push(message);
+        }
+
+        if let Err(message) = check_rollback(&expression) {
+            failures.push(message);
+        }
+
+        let ty = arbitrary_type(&mut gen, depth);
+        let first = arbitrary_substitution(&mut gen, depth);
+        let second = arbitrary_substitution(&mut gen, depth);
+        if let Err(message) = check_substitution_laws(&ty, &first, &second) {
+            failures.push(message);
+        }
+
+        let other = arbitrary_type(&mut gen, depth);
+        if let Err(message) = check_simplification(&ty, &other) {
+            failures.push(message);
+        }
+    }
+
+    if let Err(message) = check_many_constraints(MANY_CONSTRAINTS_DEPTH) {
+        failures.push(message);
+    }
+
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Random programs use every piece of syntax, sugar included
+    #[test]
+    fn formatting_round_trips() {
+        let mut gen = Gen::new(0);
+
+        for _ in 0..500 {
+            let expression = arbitrary_ast(&mut gen, 4);
+
+            assert_eq!(check_round_trip(&expression), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rolling_back_forgets_random_programs() {
+        let mut gen = Gen::new(1);
+
+        for _ in 0..200 {
+            let expression = arbitrary_ast(&mut gen, 4);
+
+            assert_eq!(check_rollback(&expression), Ok(()));
+        }
+    }
+
+    // 2^11 additions give the solver thousands of constraints at once
+    #[test]
+    fn many_constraints_dont_overflow_the_stack() {
+        assert_eq!(check_many_constraints(MANY_CONSTRAINTS_DEPTH), Ok(()));
+    }
+}
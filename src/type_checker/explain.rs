@@ -0,0 +1,198 @@
+// Why an expression has the type it has, for teaching and for types which come as a surprise
+use super::trace::TraceEvent;
+use super::type_::{Substituable, Type, TypeConstraint, TypeContext};
+use crate::messages::Locale;
+use crate::parser::{parse_expression, Span};
+use im::HashSet;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Longer source snippets get cut off when rendering
+const MAX_SNIPPET: usize = 30;
+
+#[derive(Debug, Clone)]
+pub struct TypeExplanation {
+    // The innermost expression containing the span asked about
+    pub span: Span,
+    pub ty: Type,
+    // Every constraint tied to the type of the expression through its variables,
+    // in the order they were generated
+    pub constraints: Vec<TypeConstraint>,
+}
+
+impl TypeExplanation {
+    pub fn render(self: &TypeExplanation, program: &str, locale: Locale) -> String {
+        let mut lines = vec![format!(
+            "{} has type {}",
+            snippet(program, self.span),
+            self.ty
+        )];
+
+        for constraint in &self.constraints {
+            let origin = constraint.origin();
+            let place = match origin.span {
+                Some(span) => format!(
+                    "{} at offsets {}-{}",
+                    snippet(program, span),
+                    span.start,
+                    span.end
+                ),
+                None => "Somewhere".to_string(),
+            };
+            let (left, right) = match constraint {
+                TypeConstraint::Unify(left, right, _) | TypeConstraint::Match(left, right, _) => {
+                    (left, right)
+                }
+            };
+
+            lines.push(format!(
+                "    {}: {}\n        {} ~ {}",
+                place,
+                origin.reason.localized(locale),
+                left,
+                right
+            ));
+        }
+
+        lines.join("\n")
+    }
+}
+
+// The source of a span on a single line, quoted
+fn snippet(program: &str, span: Span) -> String {
+    let text = program.get(span.start..span.end).unwrap_or("");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if text.chars().count() > MAX_SNIPPET {
+        let shortened: String = text.chars().take(MAX_SNIPPET).collect();
+        format!("`{}...`", shortened)
+    } else {
+        format!("`{}`", text)
+    }
+}
+
+// List the constraints which decided the type of the innermost expression containing a span
+pub fn explain_type(program: &str, span: Span) -> Option<TypeExplanation> {
+    explain_type_in(TypeContext::new(), program, span)
+}
+
+pub fn explain_type_in(context: TypeContext, program: &str, span: Span) -> Option<TypeExplanation> {
+    let expression = parse_expression(program).ok()?;
+
+    // Types get recorded before solving, since that's what the constraints talk about
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let recorded = events.clone();
+    let mut context = context.with_tracer(move |event| match event {
+        TraceEvent::Constraint(_) | TraceEvent::Typed(_, _) => {
+            recorded.borrow_mut().push(event.clone())
+        }
+        _ => {}
+    });
+
+    context.infer(expression).ok()?;
+    let substitution = context.solve_constraints().ok()?;
+    let events = events.borrow();
+
+    let (found, unsolved) = events
+        .iter()
+        .filter_map(|event| match event {
+            TraceEvent::Typed(found, ty) if found.start <= span.start && span.end <= found.end => {
+                Some((*found, ty))
+            }
+            _ => None,
+        })
+        .min_by_key(|(found, _)| found.end - found.start)?;
+
+    let constraints: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            TraceEvent::Constraint(constraint) => Some(constraint),
+            _ => None,
+        })
+        .collect();
+
+    // Follow the variables of the type from constraint to constraint
+    let mut variables: HashSet<String> = unsolved
+        .free_variables()
+        .into_iter()
+        .map(|variable| variable.name)
+        .collect();
+    let mut chained = vec![false; constraints.len()];
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for (index, constraint) in constraints.iter().enumerate() {
+            if chained[index] {
+                continue;
+            }
+
+            let mentioned: Vec<_> = constraint
+                .free_variables()
+                .into_iter()
+                .map(|variable| variable.name)
+                .collect();
+
+            if mentioned.iter().any(|name| variables.contains(name)) {
+                chained[index] = true;
+                variables.extend(mentioned);
+                changed = true;
+            }
+        }
+    }
+
+    Some(TypeExplanation {
+        span: found,
+        ty: unsolved.clone().apply_substitution(&substitution),
+        constraints: constraints
+            .into_iter()
+            .zip(chained)
+            .filter(|(_, chained)| *chained)
+            .map(|(constraint, _)| constraint.clone())
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explanations_follow_variables_through_constraints() {
+        let program = "\\x -> add x 1";
+        let explanation = explain_type(program, Span::new(1, 2)).unwrap();
+
+        assert_eq!(explanation.span, Span::new(0, 13));
+        assert_eq!(
+            explanation.render(program, Locale::English),
+            "`\\x -> add x 1` has type Number -> Number\n    `x` at offsets 10-11: A function has to accept the argument it is called with\n        t0 ~ Number"
+        );
+    }
+
+    // add doesn't have any variables, so nothing decided its type
+    #[test]
+    fn the_innermost_expression_gets_explained() {
+        let explanation = explain_type("\\x -> add x 1", Span::new(6, 7)).unwrap();
+
+        assert_eq!(explanation.span, Span::new(6, 9));
+        assert_eq!(explanation.ty.to_string(), "Number -> Number -> Number");
+        assert!(explanation.constraints.is_empty());
+    }
+
+    #[test]
+    fn nothing_gets_explained_outside_of_the_program() {
+        assert!(explain_type("add 1 2", Span::new(50, 60)).is_none());
+        assert!(explain_type("add 1 \"a\"", Span::new(0, 3)).is_none());
+    }
+
+    #[test]
+    fn long_snippets_get_cut_off() {
+        let program = "let value = 1 in let other = 2 in add value other";
+
+        assert_eq!(
+            snippet(program, Span::new(0, program.len())),
+            "`let value = 1 in let other = 2...`"
+        );
+    }
+}
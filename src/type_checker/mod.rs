@@ -1 +1,5 @@
+pub mod explain;
+pub mod prelude;
+pub mod trace;
 pub mod type_;
+pub mod typed_ast;
@@ -0,0 +1,352 @@
+use super::type_::{Type, TypeEnv, VarName};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// A type variable of kind *, to be quantified over by the caller
+fn variable(name: &str) -> (VarName, Type) {
+    let name = VarName {
+        name: name.to_string(),
+        kind: Rc::new(Type::star()),
+    };
+
+    (name.clone(), Type::Variable(name))
+}
+
+// a -> b -> ... -> result
+fn function(arguments: Vec<Type>, result: Type) -> Type {
+    arguments
+        .into_iter()
+        .rev()
+        .fold(result, |result, argument| {
+            Type::create_lambda(argument, result)
+        })
+}
+
+// Builtin type constructors by name, together with their kinds
+pub fn type_constructors() -> HashMap<String, Type> {
+    let constructors = vec![
+        Type::number(),
+        Type::string(),
+        Type::char(),
+        Type::boolean(),
+        Type::unit(),
+        Type::list(),
+        Type::effect(),
+        Type::reference(),
+        Type::maybe(),
+        Type::either(),
+    ];
+
+    constructors
+        .into_iter()
+        .map(|constructor| match &constructor {
+            Type::Constructor(name) => (name.name.clone(), constructor.clone()),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+// Structural equality works on anything but functions. The type can't say that, so the
+// checker looks for functions being compared once everything is solved
+pub const EQUALITY: &[&str] = &["eq", "neq"];
+
+pub fn equality() -> Type {
+    let (a, var_a) = variable("a");
+
+    function(vec![var_a.clone(), var_a], Type::boolean()).to_scheme(vec![a])
+}
+
+// The types and values every program can use without defining them
+pub fn prelude() -> TypeEnv {
+    let mut env = TypeEnv::new();
+    let (a, var_a) = variable("a");
+    let (b, var_b) = variable("b");
+    let (c, var_c) = variable("c");
+    let list = |ty: Type| Type::list().apply(ty);
+    let effect = |ty: Type| Type::effect().apply(ty);
+
+    env.insert("Type".to_string(), Type::star());
+
+    // Debugging primitives. Both just hand back their last argument,
+    // so they can be typed for any a
+    env.insert(
+        "assert".to_string(),
+        function(
+            vec![Type::boolean(), Type::string(), var_a.clone()],
+            var_a.clone(),
+        )
+        .to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "trace".to_string(),
+        function(vec![Type::string(), var_a.clone()], var_a.clone()).to_scheme(vec![a.clone()]),
+    );
+
+    env.insert("unit".to_string(), Type::unit());
+    env.insert("true".to_string(), Type::boolean());
+    env.insert("false".to_string(), Type::boolean());
+    env.insert(
+        "not".to_string(),
+        function(vec![Type::boolean()], Type::boolean()),
+    );
+
+    for name in &["and", "or"] {
+        env.insert(
+            name.to_string(),
+            function(vec![Type::boolean(), Type::boolean()], Type::boolean()),
+        );
+    }
+
+    for name in &["add", "sub", "mul", "div"] {
+        env.insert(
+            name.to_string(),
+            function(vec![Type::number(), Type::number()], Type::number()),
+        );
+    }
+
+    // What -x is sugar for
+    env.insert(
+        "negate".to_string(),
+        function(vec![Type::number()], Type::number()),
+    );
+
+    for name in &["lt", "lte", "gt", "gte"] {
+        env.insert(
+            name.to_string(),
+            function(vec![Type::number(), Type::number()], Type::boolean()),
+        );
+    }
+
+    for name in EQUALITY {
+        env.insert(name.to_string(), equality());
+    }
+
+    env.insert(
+        "concat".to_string(),
+        function(vec![Type::string(), Type::string()], Type::string()),
+    );
+    env.insert(
+        "show".to_string(),
+        function(vec![var_a.clone()], Type::string()).to_scheme(vec![a.clone()]),
+    );
+
+    // Lists
+    env.insert(
+        "nil".to_string(),
+        list(var_a.clone()).to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "cons".to_string(),
+        function(
+            vec![var_a.clone(), list(var_a.clone())],
+            list(var_a.clone()),
+        )
+        .to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "head".to_string(),
+        function(vec![list(var_a.clone())], var_a.clone()).to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "tail".to_string(),
+        function(vec![list(var_a.clone())], list(var_a.clone())).to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "isEmpty".to_string(),
+        function(vec![list(var_a.clone())], Type::boolean()).to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "length".to_string(),
+        function(vec![list(var_a.clone())], Type::number()).to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "map".to_string(),
+        function(
+            vec![
+                Type::create_lambda(var_a.clone(), var_b.clone()),
+                list(var_a.clone()),
+            ],
+            list(var_b.clone()),
+        )
+        .to_scheme(vec![a.clone(), b.clone()]),
+    );
+    env.insert(
+        "foldr".to_string(),
+        function(
+            vec![
+                function(vec![var_a.clone(), var_b.clone()], var_b.clone()),
+                var_b.clone(),
+                list(var_a.clone()),
+            ],
+            var_b.clone(),
+        )
+        .to_scheme(vec![a.clone(), b.clone()]),
+    );
+
+    // Function composition, which `f >> g` and `g << f` are sugar for
+    env.insert(
+        "compose".to_string(),
+        function(
+            vec![
+                Type::create_lambda(var_b.clone(), var_c.clone()),
+                Type::create_lambda(var_a.clone(), var_b.clone()),
+            ],
+            Type::create_lambda(var_a.clone(), var_c.clone()),
+        )
+        .to_scheme(vec![a.clone(), b.clone(), c.clone()]),
+    );
+
+    // Maybe and Either are builtin until programs can declare data types of their own.
+    // Without case expressions, maybe and either are the only way to look inside them
+    let maybe = |ty: Type| Type::maybe().apply(ty);
+    let either = |left: Type, right: Type| Type::either().apply(left).apply(right);
+
+    env.insert(
+        "Nothing".to_string(),
+        maybe(var_a.clone()).to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "Just".to_string(),
+        function(vec![var_a.clone()], maybe(var_a.clone())).to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "maybe".to_string(),
+        function(
+            vec![
+                var_b.clone(),
+                Type::create_lambda(var_a.clone(), var_b.clone()),
+                maybe(var_a.clone()),
+            ],
+            var_b.clone(),
+        )
+        .to_scheme(vec![a.clone(), b.clone()]),
+    );
+    env.insert(
+        "Left".to_string(),
+        function(vec![var_a.clone()], either(var_a.clone(), var_b.clone()))
+            .to_scheme(vec![a.clone(), b.clone()]),
+    );
+    env.insert(
+        "Right".to_string(),
+        function(vec![var_b.clone()], either(var_a.clone(), var_b.clone()))
+            .to_scheme(vec![a.clone(), b.clone()]),
+    );
+    env.insert(
+        "either".to_string(),
+        function(
+            vec![
+                Type::create_lambda(var_a.clone(), var_c.clone()),
+                Type::create_lambda(var_b.clone(), var_c.clone()),
+                either(var_a.clone(), var_b.clone()),
+            ],
+            var_c,
+        )
+        .to_scheme(vec![a.clone(), b.clone(), c]),
+    );
+
+    // Effects. Anything talking to the outside world has to return one of these
+    env.insert(
+        "pure".to_string(),
+        function(vec![var_a.clone()], effect(var_a.clone())).to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "bind".to_string(),
+        function(
+            vec![
+                effect(var_a.clone()),
+                Type::create_lambda(var_a.clone(), effect(var_b.clone())),
+            ],
+            effect(var_b),
+        )
+        .to_scheme(vec![a.clone(), b]),
+    );
+    env.insert(
+        "print".to_string(),
+        function(vec![Type::string()], effect(Type::unit())),
+    );
+    env.insert("readLine".to_string(), effect(Type::string()));
+
+    // Mutable references, typed only like the rest. Making one is an effect, so a do block
+    // binds it to a single, monomorphic type instead of letting it be generalized
+    let reference = |ty: Type| Type::reference().apply(ty);
+    env.insert(
+        "newRef".to_string(),
+        function(vec![var_a.clone()], effect(reference(var_a.clone()))).to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "readRef".to_string(),
+        function(vec![reference(var_a.clone())], effect(var_a.clone())).to_scheme(vec![a.clone()]),
+    );
+    env.insert(
+        "writeRef".to_string(),
+        function(vec![reference(var_a.clone()), var_a], effect(Type::unit())).to_scheme(vec![a]),
+    );
+
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_expression;
+    use crate::type_checker::type_::{
+        get_type_of, get_type_or_errors_in, Generalization, TypeContext, TypeError,
+    };
+
+    fn type_of(source: &str) -> Result<String, TypeError> {
+        get_type_of(parse_expression(source).unwrap()).map(|ty| ty.to_string())
+    }
+
+    #[test]
+    fn maybes_get_built_and_taken_apart() {
+        assert_eq!(type_of("Just").unwrap(), "forall a. a -> Maybe a");
+        assert_eq!(type_of("maybe 0 (add 1) (Just 2)").unwrap(), "Number");
+        assert_eq!(type_of("maybe 0 (add 1) Nothing").unwrap(), "Number");
+        assert!(type_of("maybe 0 (add 1) (Just \"a\")").is_err());
+    }
+
+    #[test]
+    fn eithers_get_built_and_taken_apart() {
+        assert_eq!(
+            type_of("(Right 1 :: Either String Number)").unwrap(),
+            "Either String Number"
+        );
+        assert_eq!(
+            type_of("either (\\s -> 0) (add 1) (Left \"a\")").unwrap(),
+            "Number"
+        );
+        assert!(type_of("either length (add 1) (Left \"a\")").is_err());
+    }
+
+    #[test]
+    fn maybe_needs_an_argument() {
+        match type_of("(Nothing :: Maybe)") {
+            Err(TypeError::KindMismatch(_, _, _)) => {}
+            result => panic!("Expected a kind error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn references_hold_what_they_were_made_with() {
+        assert_eq!(
+            type_of("do { r <- newRef 0; writeRef r 1; readRef r }").unwrap(),
+            "Effect Number"
+        );
+        assert!(type_of("do { r <- newRef 0; writeRef r \"a\" }").is_err());
+    }
+
+    // References bound in do blocks are lambda parameters, so they never get generalized
+    #[test]
+    fn references_stay_monomorphic() {
+        for generalization in [
+            Generalization::All,
+            Generalization::Values,
+            Generalization::Never,
+        ] {
+            let source =
+                "do { r <- newRef nil; writeRef r (cons 1 nil); writeRef r (cons \"a\" nil) }";
+            let context = TypeContext::new().with_generalization(generalization);
+
+            assert!(get_type_or_errors_in(context, parse_expression(source).unwrap()).is_err());
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// What the type checker did to get to a type, for embedders and for debugging inference
+use super::type_::{Substitution, Type, TypeConstraint, TypeError};
+use crate::parser::Span;
+use std::rc::Rc;
+use std::{
+    fmt,
+    fmt::{Display, Formatter},
+};
+
+// Something the type checker did, for embedders who want to see how it got to a type
+#[derive(Debug, Clone)]
+pub enum TraceEvent {
+    // A constraint was generated while inferring
+    Constraint(TypeConstraint),
+    // An expression got a type, before any solving
+    Typed(Span, Type),
+    // A step of unifying two types, nested steps included
+    Unify(Type, Type),
+    Match(Type, Type),
+    // Solving a constraint found out about some variables
+    Merge(Substitution),
+}
+
+impl Display for TraceEvent {
+    fn fmt(self: &Self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceEvent::Constraint(constraint) => write!(f, "constraint {}", constraint),
+            TraceEvent::Typed(span, ty) => write!(
+                f,
+                "expression at offsets {}-{} has type {}",
+                span.start, span.end, ty
+            ),
+            TraceEvent::Unify(left, right) => write!(f, "unify {} with {}", left, right),
+            TraceEvent::Match(left, right) => write!(f, "match {} with {}", left, right),
+            TraceEvent::Merge(substitution) => {
+                let mut bindings: Vec<_> = substitution
+                    .iter()
+                    .map(|(variable, ty)| format!("{} = {}", variable, ty))
+                    .collect();
+                bindings.sort();
+
+                write!(f, "solved {}", bindings.join(", "))
+            }
+        }
+    }
+}
+
+type Tracer = dyn Fn(&TraceEvent);
+
+#[derive(Clone)]
+pub struct TraceHandler(Rc<Tracer>);
+
+impl fmt::Debug for TraceHandler {
+    fn fmt(self: &Self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "TraceHandler")
+    }
+}
+
+impl TraceHandler {
+    pub fn new(tracer: impl Fn(&TraceEvent) + 'static) -> TraceHandler {
+        TraceHandler(Rc::new(tracer))
+    }
+
+    pub fn call(self: &TraceHandler, event: &TraceEvent) {
+        (self.0)(event)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    Unify,
+    Match,
+}
+
+// A single unification or matching, together with the steps it took to get there
+#[derive(Debug, Clone)]
+pub struct TraceStep {
+    pub kind: StepKind,
+    pub left: Type,
+    pub right: Type,
+    pub result: Result<Substitution, TypeError>,
+    pub children: Vec<TraceStep>,
+}
+
+impl TraceStep {
+    // One line per step, with the steps it needed indented below it
+    pub fn render(self: &TraceStep) -> String {
+        let verb = match self.kind {
+            StepKind::Unify => "unify",
+            StepKind::Match => "match",
+        };
+        let result = match &self.result {
+            Ok(substitution) if substitution.is_empty() => "nothing to solve".to_string(),
+            Ok(substitution) => {
+                let mut bindings: Vec<_> = substitution
+                    .iter()
+                    .map(|(variable, ty)| format!("{} = {}", variable, ty))
+                    .collect();
+                bindings.sort();
+                bindings.join(", ")
+            }
+            Err(error) => format!("failed with {}", error.code()),
+        };
+
+        let mut lines = vec![format!(
+            "{} {} with {} => {}",
+            verb, self.left, self.right, result
+        )];
+
+        for child in &self.children {
+            lines.push(format!("  {}", child.render().replace('\n', "\n  ")));
+        }
+
+        lines.join("\n")
+    }
+}
+
+// Builds the tree of steps out of steps starting and ending
+#[derive(Debug, Default)]
+pub struct Recorder {
+    finished: Vec<TraceStep>,
+    // Steps which started but didn't end yet, innermost last
+    open: Vec<TraceStep>,
+}
+
+impl Recorder {
+    pub fn begin(self: &mut Recorder, kind: StepKind, left: &Type, right: &Type) {
+        self.open.push(TraceStep {
+            kind,
+            left: left.clone(),
+            right: right.clone(),
+            result: Ok(Substitution::new()),
+            children: Vec::new(),
+        })
+    }
+
+    pub fn end(self: &mut Recorder, result: &Result<Substitution, TypeError>) {
+        if let Some(mut step) = self.open.pop() {
+            step.result = result.clone();
+
+            match self.open.last_mut() {
+                Some(parent) => parent.children.push(step),
+                None => self.finished.push(step),
+            }
+        }
+    }
+
+    // Every outermost step so far, in the order they happened
+    pub fn steps(self: &Recorder) -> &[TraceStep] {
+        &self.finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_expression;
+    use crate::type_checker::type_::TypeContext;
+
+    // The steps solving the constraints of a program took
+    fn trace(source: &str) -> Vec<String> {
+        let mut context = TypeContext::new().with_tracing();
+        context.infer(parse_expression(source).unwrap()).unwrap();
+        let _ = context.solve_constraints();
+
+        context
+            .solver_trace()
+            .iter()
+            .map(TraceStep::render)
+            .collect()
+    }
+
+    // Unifying the variables also unifies their kinds, which shows up as a nested step
+    #[test]
+    fn steps_keep_the_steps_they_needed() {
+        assert_eq!(
+            trace("\\x -> add x 1"),
+            vec!["unify t0 with Number => t0 = Number\n  unify * with * => nothing to solve"]
+        );
+    }
+
+    #[test]
+    fn failed_steps_keep_their_error() {
+        assert_eq!(
+            trace("add 1 \"a\""),
+            vec!["unify String with Number => failed with E0001"]
+        );
+    }
+
+    // Steps taken while solving the value of a let show up as well
+    #[test]
+    fn lets_share_the_recorder() {
+        assert_eq!(
+            trace("let f = \\x -> add x 1 in f"),
+            vec!["unify t0 with Number => t0 = Number\n  unify * with * => nothing to solve"]
+        );
+    }
+}
@@ -1,8 +1,14 @@
-use crate::parser::Ast;
+use super::prelude::{self, prelude, type_constructors};
+use super::trace::{Recorder, StepKind, TraceEvent, TraceHandler, TraceStep};
+use super::typed_ast::{TypedAst, TypedNode};
+use crate::messages::{self, Locale};
+use crate::parser::{parse_expression, Ast, Span};
 use im::{hashset, HashSet};
+use std::cell::RefCell;
 use std::cmp::max;
 use std::collections::HashMap;
-use std::iter::FromIterator;
+use std::mem;
+use std::rc::Rc;
 use std::{
     fmt,
     fmt::{Display, Formatter},
@@ -11,12 +17,20 @@ use std::{
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct VarName {
     pub name: String,
-    pub kind: Box<Type>,
+    pub kind: Rc<Type>,
+}
+
+impl VarName {
+    // Wildcards in annotations get a name no user written variable can have
+    pub fn is_wildcard(self: &Self) -> bool {
+        self.name.starts_with('_')
+    }
 }
 
 impl Display for VarName {
     fn fmt(self: &Self, f: &mut Formatter<'_>) -> fmt::Result {
-        if *self.kind == Type::NoKind {
+        // * is the kind almost everything has, so it is left implicit
+        if *self.kind == Type::NoKind || *self.kind == Type::star() {
             write!(f, "{}", self.name)
         } else {
             write!(f, "({} :: {})", self.name, self.kind)
@@ -27,15 +41,17 @@ impl Display for VarName {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type {
     Constructor(VarName),
-    TApply(Box<Type>, Box<Type>),
+    TApply(Rc<Type>, Rc<Type>),
     Variable(VarName),
     NoKind,
     // The reason this exists is because its definition needs itself to exist
     ArrowKind,
     Scheme {
         variables: Vec<VarName>,
-        ty: Box<Type>,
+        ty: Rc<Type>,
     },
+    // Stands in for the type of something which failed to type check, and unifies with anything
+    Error,
 }
 
 impl Type {
@@ -43,7 +59,7 @@ impl Type {
     pub fn star() -> Type {
         Type::Constructor(VarName {
             name: "*".to_string(),
-            kind: Box::new(Type::NoKind),
+            kind: Rc::new(Type::NoKind),
         })
     }
 
@@ -59,20 +75,25 @@ impl Type {
     }
 
     pub fn apply(self: &Type, other: Type) -> Type {
-        Type::TApply(Box::new(self.clone()), Box::new(other))
+        Type::TApply(Rc::new(self.clone()), Rc::new(other))
     }
 
     pub fn from_string(name: String) -> Type {
         Type::Variable(VarName {
             name,
-            kind: Box::new(Type::NoKind),
+            kind: Rc::new(Type::NoKind),
         })
     }
 
+    // A `_` written at some offset in the source
+    pub fn wildcard(offset: usize) -> Type {
+        Type::from_string(format!("_{}", offset))
+    }
+
     pub fn to_scheme(self: &Type, variables: Vec<VarName>) -> Type {
         Type::Scheme {
             variables,
-            ty: Box::new(self.clone()),
+            ty: Rc::new(self.clone()),
         }
     }
 
@@ -85,7 +106,7 @@ impl Type {
     pub fn constant(name: &str) -> Type {
         Type::Constructor(VarName {
             name: String::from(name),
-            kind: Box::new(Type::star()),
+            kind: Rc::new(Type::star()),
         })
     }
 
@@ -99,11 +120,115 @@ impl Type {
         Type::constant("String")
     }
 
+    #[inline]
+    pub fn char() -> Type {
+        Type::constant("Char")
+    }
+
     #[inline]
     pub fn boolean() -> Type {
         Type::constant("Boolean")
     }
 
+    #[inline]
+    pub fn unit() -> Type {
+        Type::constant("Unit")
+    }
+
+    // List :: * -> *
+    pub fn list() -> Type {
+        Type::Constructor(VarName {
+            name: String::from("List"),
+            kind: Rc::new(Type::create_lambda(Type::star(), Type::star())),
+        })
+    }
+
+    // Effect :: * -> *, for computations which interact with the outside world
+    pub fn effect() -> Type {
+        Type::Constructor(VarName {
+            name: String::from("Effect"),
+            kind: Rc::new(Type::create_lambda(Type::star(), Type::star())),
+        })
+    }
+
+    // Ref :: * -> *, for mutable cells. Only effects can make, read or write them
+    pub fn reference() -> Type {
+        Type::Constructor(VarName {
+            name: String::from("Ref"),
+            kind: Rc::new(Type::create_lambda(Type::star(), Type::star())),
+        })
+    }
+
+    // Maybe :: * -> *, for values which might be missing
+    pub fn maybe() -> Type {
+        Type::Constructor(VarName {
+            name: String::from("Maybe"),
+            kind: Rc::new(Type::create_lambda(Type::star(), Type::star())),
+        })
+    }
+
+    // Either :: * -> * -> *, for values of one type or the other, usually an error or a result
+    pub fn either() -> Type {
+        Type::Constructor(VarName {
+            name: String::from("Either"),
+            kind: Rc::new(Type::create_lambda(
+                Type::star(),
+                Type::create_lambda(Type::star(), Type::star()),
+            )),
+        })
+    }
+
+    // How many types deep this one is nested, kinds aside. Walks the type with a stack of
+    // its own, since the point is to notice types too deep to recurse over
+    pub fn depth(self: &Type) -> usize {
+        let mut deepest = 0;
+        let mut pending = vec![(self, 1)];
+
+        while let Some((ty, depth)) = pending.pop() {
+            deepest = max(deepest, depth);
+
+            match ty {
+                Type::TApply(fun, input) => {
+                    pending.push((fun, depth + 1));
+                    pending.push((input, depth + 1));
+                }
+                Type::Scheme { ty, .. } => pending.push((ty, depth + 1)),
+                _ => {}
+            }
+        }
+
+        deepest
+    }
+
+    // The type with a substitution applied, or None when that changes nothing. Subtrees
+    // which stay the same keep getting shared instead of being copied
+    fn substituted(self: &Type, substitution: &Substitution) -> Option<Type> {
+        match self {
+            Type::Variable(VarName { name, kind }) => match substitution.get(name) {
+                Some(new_type) => Some(new_type.clone()),
+                None => kind.substituted(substitution).map(|kind| {
+                    Type::Variable(VarName {
+                        name: name.clone(),
+                        kind: Rc::new(kind),
+                    })
+                }),
+            },
+            Type::TApply(fun, input) => {
+                match (
+                    fun.substituted(substitution),
+                    input.substituted(substitution),
+                ) {
+                    (None, None) => None,
+                    (new_fun, new_input) => Some(Type::TApply(
+                        new_fun.map_or_else(|| fun.clone(), Rc::new),
+                        new_input.map_or_else(|| input.clone(), Rc::new),
+                    )),
+                }
+            }
+            _ => None,
+        }
+    }
+
     // Returns true if the type has a reference to itself
     pub fn is_recursive(self: &Type, variable: &String) -> bool {
         self.free_variables()
@@ -112,35 +237,341 @@ impl Type {
             .is_some()
     }
 
-    // Checks if this is a polymorphic type
-    pub fn is_scheme(self: &Type) -> bool {
+    // A rigid stand-in for a variable written in an annotation. It can't be bound to anything,
+    // and the id keeps it apart from skolems of other annotations
+    pub fn skolem(variable: &VarName, id: u32) -> Type {
+        Type::Constructor(VarName {
+            name: format!("{}'{}", variable.name, id),
+            kind: variable.kind.clone(),
+        })
+    }
+
+    // Same as checking free_variables is empty, without building the set
+    pub fn is_closed(self: &Type) -> bool {
+        match self {
+            Type::Variable(_) => false,
+            Type::TApply(fun, input) => fun.is_closed() && input.is_closed(),
+            Type::Scheme { .. } => self.free_variables().is_empty(),
+            _ => true,
+        }
+    }
+
+    // Constructors written by the user always start with an uppercase letter
+    pub fn is_skolem(self: &Type) -> bool {
         match self {
-            Type::Scheme {
-                variables: _,
-                ty: _,
-            } => true,
+            Type::Constructor(VarName { name, .. }) => name.starts_with(char::is_lowercase),
             _ => false,
         }
     }
 
+    // Every skolem mentioned by this type
+    pub fn skolems(self: &Type) -> HashSet<String> {
+        match self {
+            Type::Constructor(VarName { name, .. }) if self.is_skolem() => hashset![name.clone()],
+            Type::TApply(fun, input) => fun.skolems().union(input.skolems()),
+            Type::Scheme { ty, .. } => ty.skolems(),
+            _ => HashSet::new(),
+        }
+    }
+
+    // Annotations quantify over every variable they mention, except for wildcards
+    pub fn quantify_annotation(self: Type) -> Type {
+        let (mut variables, ty) = match self {
+            Type::Scheme { variables, ty } => (variables, Rc::unwrap_or_clone(ty)),
+            other => (Vec::new(), other),
+        };
+
+        for variable in ty.free_variables() {
+            if !variable.is_wildcard() && !variables.contains(&variable) {
+                variables.push(variable);
+            }
+        }
+
+        ty.to_scheme(variables)
+    }
+
+    // Checks if this is a polymorphic type
+    pub fn is_scheme(self: &Type) -> bool {
+        matches!(self, Type::Scheme { .. })
+    }
+
+    // Quantify over every variable created inside of the let the context is at.
+    // Variables the environment can reach had their levels lowered, so they stay free
     pub fn generalize(self: &Type, context: &TypeContext) -> Type {
-        let quantifiers = self
+        let generalizable = |variable: &VarName| context.level_of(&variable.name) > context.level;
+        let variables: Vec<VarName> = self
             .clone()
             .free_variables()
+            .into_iter()
+            .filter(generalizable)
+            .collect();
+
+        // Unsolved kinds of those variables get quantified as well, before the variables using them
+        let kind_variables: HashSet<VarName> = variables
             .iter()
-            .filter(|variable| !context.environment.contains_key(&variable.name))
-            .map(Clone::clone)
+            .flat_map(|variable| variable.kind.free_variables())
+            .filter(generalizable)
             .collect();
 
+        let mut quantifiers: Vec<VarName> = kind_variables.into_iter().collect();
+        quantifiers.extend(variables);
+
         self.to_scheme(quantifiers)
     }
 
+    // Variables in the order they show up in when the type is printed
+    fn variables_in_order(self: &Type, found: &mut Vec<VarName>) {
+        match self {
+            Type::Variable(var) if found.iter().all(|other| other.name != var.name) => {
+                found.push(var.clone())
+            }
+            Type::TApply(fun, input) => {
+                fun.variables_in_order(found);
+                input.variables_in_order(found);
+            }
+            Type::Scheme { ty, .. } => ty.variables_in_order(found),
+            _ => {}
+        }
+    }
+
+    // Same as variables_in_order, but the variables schemes quantify over go into their own list
+    fn free_variables_in_order(
+        self: &Type,
+        bound: &[VarName],
+        found: &mut Vec<VarName>,
+        quantified: &mut Vec<VarName>,
+    ) {
+        match self {
+            Type::Variable(var)
+                if bound.iter().all(|other| other.name != var.name)
+                    && found.iter().all(|other| other.name != var.name) =>
+            {
+                found.push(var.clone())
+            }
+            Type::TApply(fun, input) => {
+                fun.free_variables_in_order(bound, found, quantified);
+                input.free_variables_in_order(bound, found, quantified);
+            }
+            Type::Scheme { variables, ty } => {
+                let bound = [bound, variables.as_slice()].concat();
+                quantified.extend(variables.iter().cloned());
+                ty.free_variables_in_order(&bound, found, quantified);
+            }
+            _ => {}
+        }
+    }
+
+    // Applies a renaming from stable_names, or kinds solved by infer_kinds. Unlike
+    // substituting, this also renames what schemes don't quantify over. Quantified
+    // variables named like something the renaming introduces would capture it, so those
+    // get primes until they don't
+    fn renamed(self: Type, renaming: &Substitution) -> Type {
+        match self {
+            Type::TApply(fun, input) => Type::TApply(
+                Rc::new(Rc::unwrap_or_clone(fun).renamed(renaming)),
+                Rc::new(Rc::unwrap_or_clone(input).renamed(renaming)),
+            ),
+            Type::Scheme { variables, ty } => {
+                let mut inner = renaming.clone();
+                for var in &variables {
+                    inner.remove(&var.name);
+                }
+
+                let introduced: HashSet<String> = ty
+                    .free_variables()
+                    .into_iter()
+                    .chain(variables.iter().flat_map(|var| var.kind.free_variables()))
+                    .filter_map(|var| inner.get(&var.name))
+                    .flat_map(Type::free_variables)
+                    .map(|var| var.name)
+                    .collect();
+                let is_free = |name: &String| {
+                    introduced.contains(name) || variables.iter().any(|var| &var.name == name)
+                };
+
+                let mut primed = HashMap::new();
+                for var in &variables {
+                    if introduced.contains(&var.name) {
+                        let mut name = format!("{}'", var.name);
+                        while is_free(&name) {
+                            name.push('\'');
+                        }
+
+                        // Kind variables have no kinds, so these are already right for them
+                        inner.insert(
+                            var.name.clone(),
+                            Type::Variable(VarName {
+                                name: name.clone(),
+                                ..var.clone()
+                            }),
+                        );
+                        primed.insert(var.name.clone(), name);
+                    }
+                }
+
+                let variables: Vec<_> = variables
+                    .into_iter()
+                    .map(|var| VarName {
+                        kind: Rc::new(var.kind.as_ref().clone().renamed(&inner)),
+                        name: primed.get(&var.name).cloned().unwrap_or(var.name),
+                    })
+                    .collect();
+
+                for var in &variables {
+                    if let Some((old, _)) = primed.iter().find(|(_, name)| **name == var.name) {
+                        inner.insert(old.clone(), Type::Variable(var.clone()));
+                    }
+                }
+
+                Type::Scheme {
+                    variables,
+                    ty: Rc::new(Rc::unwrap_or_clone(ty).renamed(&inner)),
+                }
+            }
+            other => other.apply_substitution(renaming),
+        }
+    }
+
+    // Names the user sees for constructors, so renamed variables don't look like them
+    fn constructor_names(self: &Type, found: &mut HashSet<String>) {
+        match self {
+            Type::Constructor(_) => {
+                found.insert(self.to_string());
+            }
+            Type::TApply(fun, input) => {
+                fun.constructor_names(found);
+                input.constructor_names(found);
+            }
+            Type::Scheme { ty, .. } => ty.constructor_names(found),
+            _ => {}
+        }
+    }
+
+    // Rename the quantified variables of a scheme to a, b, c, ... in the order they show up in
+    pub fn normalize(self: Type) -> Type {
+        match self {
+            Type::Scheme { variables, ty } => {
+                let quantified = |name: &String| variables.iter().any(|var| &var.name == name);
+
+                // Kind variables only show up in the kinds of other variables, so they come last
+                let mut found = Vec::new();
+                ty.variables_in_order(&mut found);
+                for var in &variables {
+                    var.kind.variables_in_order(&mut found);
+                }
+
+                let mut order: Vec<_> = found
+                    .iter()
+                    .filter_map(|found| variables.iter().find(|var| var.name == found.name))
+                    .cloned()
+                    .collect();
+                for var in &variables {
+                    if !order.contains(var) {
+                        order.push(var.clone());
+                    }
+                }
+
+                let mut taken = HashSet::new();
+                ty.constructor_names(&mut taken);
+                for var in ty.free_variables() {
+                    if !quantified(&var.name) {
+                        taken.insert(var.name);
+                    }
+                }
+
+                let (substitution, mut variables) = readable_names(&order, &taken);
+                let ty = Rc::unwrap_or_clone(ty).apply_substitution(&substitution);
+
+                // Kind variables still get quantified before the variables using them
+                variables.sort_by_key(|var| *var.kind != Type::NoKind);
+
+                ty.to_scheme(variables)
+            }
+            other => other,
+        }
+    }
+
+    // Rename the variables of types shown together to a, b, c, ... the same way in all of them
+    pub fn normalize_together(types: Vec<Type>) -> Vec<Type> {
+        let mut order = Vec::new();
+        let mut taken = HashSet::new();
+
+        for ty in &types {
+            ty.variables_in_order(&mut order);
+            ty.constructor_names(&mut taken);
+        }
+
+        // Wildcards are printed as _ anyway
+        order.retain(|var| !var.is_wildcard());
+
+        let (substitution, _) = readable_names(&order, &taken);
+
+        types
+            .into_iter()
+            .map(|ty| ty.apply_substitution(&substitution))
+            .collect()
+    }
+
+    // The type at the bottom of a chain of applications, and how many arguments it gets
+    fn head(self: &Type) -> (&Type, usize) {
+        match self {
+            Type::TApply(fun, _) => {
+                let (head, arguments) = fun.head();
+                (head, arguments + 1)
+            }
+            other => (other, 0),
+        }
+    }
+
+    // The smallest parts where two types differ, each together with the left type where
+    // that part is replaced by `_`. Variables could still become anything, so they never differ
+    pub fn differences(self: &Type, other: &Type) -> Vec<(Type, Type, Type)> {
+        let hole = Type::Constructor(VarName {
+            name: "_".to_string(),
+            kind: Rc::new(Type::star()),
+        });
+
+        match (self, other) {
+            (left, right) if left == right => vec![],
+            (Type::Variable(_), _) | (_, Type::Variable(_)) => vec![],
+            (Type::Error, _) | (_, Type::Error) => vec![],
+            (Type::TApply(fun_left, input_left), Type::TApply(fun_right, input_right))
+                if self.head().1 == other.head().1 =>
+            {
+                let in_function =
+                    fun_left
+                        .differences(fun_right)
+                        .into_iter()
+                        .map(|(left, right, context)| {
+                            (left, right, context.apply(input_left.as_ref().clone()))
+                        });
+                let in_input = input_left
+                    .differences(input_right)
+                    .into_iter()
+                    .map(|(left, right, context)| (left, right, fun_left.apply(context)));
+
+                in_function.chain(in_input).collect()
+            }
+            (left, right) => vec![(left.clone(), right.clone(), hole)],
+        }
+    }
+
+    // Whether there is a function anywhere inside this type
+    pub fn contains_function(self: &Type) -> bool {
+        match self {
+            Type::ArrowKind => true,
+            Type::TApply(fun, input) => fun.contains_function() || input.contains_function(),
+            Type::Scheme { ty, .. } => ty.contains_function(),
+            _ => false,
+        }
+    }
+
     // Check if a type is a function
     pub fn unwrap_function(self: &Type) -> Option<(Type, Type)> {
         if let Type::TApply(first, to) = self {
-            if let Type::TApply(fun, from) = *first.clone() {
+            if let Type::TApply(fun, from) = first.as_ref().clone() {
                 if *fun == Type::ArrowKind {
-                    Some((*from, *to.clone()))
+                    Some((Rc::unwrap_or_clone(from), to.as_ref().clone()))
                 } else {
                     None
                 }
@@ -153,31 +584,149 @@ impl Type {
     }
 }
 
+// Pick the names a, b, ..., z, a1, b1, ... for some variables, skipping the taken ones
+fn readable_names(variables: &[VarName], taken: &HashSet<String>) -> (Substitution, Vec<VarName>) {
+    let mut names = (0..)
+        .map(|index: usize| {
+            let letter = (b'a' + (index % 26) as u8) as char;
+
+            match index / 26 {
+                0 => letter.to_string(),
+                round => format!("{}{}", letter, round),
+            }
+        })
+        .filter(|name| !taken.contains(name));
+
+    let named: Vec<_> = variables
+        .iter()
+        .map(|var| (var, names.next().unwrap()))
+        .collect();
+
+    // Kinds can mention the other variables, so they get renamed once all names are known
+    let kind_substitution: Substitution = named
+        .iter()
+        .map(|(var, name)| (var.name.clone(), Type::from_string(name.clone())))
+        .collect();
+
+    let renamed: Vec<_> = named
+        .into_iter()
+        .map(|(var, name)| VarName {
+            name,
+            kind: Rc::new(
+                var.kind
+                    .as_ref()
+                    .clone()
+                    .apply_substitution(&kind_substitution),
+            ),
+        })
+        .collect();
+
+    let substitution = variables
+        .iter()
+        .zip(&renamed)
+        .map(|(var, new)| (var.name.clone(), Type::Variable(new.clone())))
+        .collect();
+
+    (substitution, renamed)
+}
+
+// Fresh variables all come from one counter, so the names of the ones left after solving
+// depend on everything inferred before them, down to how many variables each use of the
+// prelude needed. Renaming them to a, b, ... in the order they show up in means the names
+// only change when the types showing them do. Kind variables come after all the others
+pub fn stable_names(types: &[&Type]) -> Substitution {
+    stable_renaming(types).1
+}
+
+// The variables stable_names renames in the order they get their names, and the renaming
+fn stable_renaming(types: &[&Type]) -> (Vec<VarName>, Substitution) {
+    let mut found = Vec::new();
+    let mut quantified = Vec::new();
+    let mut taken = HashSet::new();
+
+    for ty in types {
+        ty.free_variables_in_order(&[], &mut found, &mut quantified);
+        ty.constructor_names(&mut taken);
+    }
+
+    // Kind variables only show up in the kinds of other variables, so they come last
+    for var in found.clone().iter().chain(&quantified) {
+        var.kind
+            .free_variables_in_order(&quantified, &mut found, &mut Vec::new());
+    }
+
+    found.sort_by_key(|var| *var.kind == Type::NoKind);
+
+    let (renaming, _) = readable_names(&found, &taken);
+    (found, renaming)
+}
+
+// Fresh variables are named t or k followed by their id
+fn is_generated(name: &str) -> bool {
+    let mut characters = name.chars();
+
+    matches!(characters.next(), Some('t' | 'k'))
+        && !characters.as_str().is_empty()
+        && characters.all(|character| character.is_ascii_digit())
+}
+
+// Same as stable_names, but only for the variables the checker made up, wildcards included.
+// Errors talk about the code the user wrote, so named variables from annotations keep the
+// names written there
+fn generated_names(types: &[&Type]) -> Substitution {
+    let (found, _) = stable_renaming(types);
+    let (generated, written): (Vec<_>, Vec<_>) = found
+        .into_iter()
+        .partition(|var| var.is_wildcard() || is_generated(&var.name));
+
+    let mut taken = HashSet::new();
+    for ty in types {
+        ty.constructor_names(&mut taken);
+    }
+    taken.extend(written.into_iter().map(|var| var.name));
+
+    readable_names(&generated, &taken).0
+}
+
+// A typed tree with its variables renamed by stable_names
+fn with_stable_names(typed: TypedAst) -> TypedAst {
+    let renaming = stable_names(&typed.types());
+    typed.map_types(&|ty| ty.renamed(&renaming))
+}
+
 impl Display for Type {
     fn fmt(self: &Type, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
+            Type::Variable(name) if name.is_wildcard() => write!(f, "_"),
             Type::Variable(name) => write!(f, "{}", name.name),
             Type::NoKind => write!(f, "[no kind]"),
+            Type::Error => write!(f, "[error]"),
             ty if ty.unwrap_function().is_some() => {
                 let (from, to) = ty.unwrap_function().unwrap();
 
-                if from.unwrap_function().is_some() {
+                if from.unwrap_function().is_some() || from.is_scheme() {
                     write!(f, "({}) -> {}", from, to)
                 } else {
                     write!(f, "{} -> {}", from, to)
                 }
             }
+            // Only shows up in kinds, when an arrow is missing its result
+            Type::TApply(fun, input) if **fun == Type::ArrowKind => write!(f, "({} ->)", input),
             Type::TApply(fun, input) => {
-                if let Type::TApply(_, _) = **input {
+                if let Type::TApply(_, _) | Type::Scheme { .. } = **input {
                     write!(f, "{} ({})", fun, input)
                 } else {
                     write!(f, "{} {}", fun, input)
                 }
             }
+            // Skolems are shown with the name the user gave them
+            Type::Constructor(VarName { name, kind: _ }) if self.is_skolem() => {
+                write!(f, "{}", &name[..name.rfind('\'').unwrap_or(name.len())])
+            }
             Type::Constructor(VarName { name, kind: _ }) => write!(f, "{}", name),
             Type::ArrowKind => write!(f, "kind(->)"),
             Type::Scheme { variables, ty } => {
-                if variables.len() == 0 {
+                if variables.is_empty() {
                     write!(f, "{}", ty)
                 } else {
                     write!(
@@ -196,169 +745,1176 @@ impl Display for Type {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TypeError {
     UnificationError(Type, Type),
     MatchingError(Type, Type),
     SubstitutionConflict(String, Type, Type),
-    NotInScope(String),
+    // The name together with similarly named things which are in scope
+    NotInScope(String, Vec<String>),
     RecursiveType(String, Type),
     // This uses Boxes so I don't have to do some random unwrapping in the unify_many function
     DifferentLengths(Vec<Type>, Vec<Type>),
+    // A type with a kind which can't be the one it needs. Holds the type, the kind it
+    // needs and the kind it has
+    KindMismatch(Type, Type, Type),
+    // A variable from an annotation ended up in a type outside of that annotation
+    EscapedSkolem(Type),
+    // No handler knows what to do with a `#...` literal
+    UnknownLiteral(String),
+    // A variable would stand for a type nested deeper than MAX_TYPE_DEPTH. Holds the
+    // variable and how deep the type is
+    TypeTooDeep(String, usize),
+    // eq or neq used on a type with functions inside, which can't be compared
+    NotEquatable(Type),
 }
 
-impl Display for TypeError {
-    fn fmt(self: &TypeError, f: &mut Formatter<'_>) -> fmt::Result {
+impl TypeError {
+    // Stable identifier of the kind of error, which is also its key in the message catalog.
+    // Every code needs an entry in error_codes, which `steiner explain` prints
+    pub fn code(self: &TypeError) -> &'static str {
+        match self {
+            TypeError::UnificationError(_, _) => "E0001",
+            TypeError::RecursiveType(_, _) => "E0002",
+            TypeError::MatchingError(_, _) => "E0003",
+            TypeError::NotInScope(_, _) => "E0004",
+            TypeError::SubstitutionConflict(_, _, _) => "E0005",
+            TypeError::DifferentLengths(_, _) => "E0006",
+            TypeError::KindMismatch(_, _, _) => "E0007",
+            TypeError::EscapedSkolem(_) => "E0008",
+            TypeError::UnknownLiteral(_) => "E0009",
+            TypeError::TypeTooDeep(_, _) => "E0010",
+            TypeError::NotEquatable(_) => "E0011",
+        }
+    }
+
+    // Every type the message shows, in the order it shows them. Variables the error only
+    // knows the name of count as well
+    fn types(self: &TypeError) -> Vec<Type> {
+        let named = |name: &String| {
+            Type::Variable(VarName {
+                name: name.clone(),
+                kind: Rc::new(Type::star()),
+            })
+        };
+
         match self {
-            TypeError::UnificationError(t1, t2) => {
-                write!(f, "Cannot unify type\n    {}\nwith type\n    {}", t1, t2)
+            TypeError::UnificationError(left, right) | TypeError::MatchingError(left, right) => {
+                vec![left.clone(), right.clone()]
             }
-            TypeError::MatchingError(t1, t2) => {
-                write!(f, "Cannot match type\n    {}\nwith type\n    {}", t1, t2)
+            TypeError::SubstitutionConflict(name, left, right) => {
+                vec![named(name), left.clone(), right.clone()]
             }
-            TypeError::SubstitutionConflict(key,t1, t2) => {
-                write!(f, "Conflicting substitutions: \n    {} = {}\nand\n    {0} = {}", key,t1, t2)
+            TypeError::RecursiveType(name, ty) => vec![named(name), ty.clone()],
+            TypeError::DifferentLengths(left, right) => {
+                left.iter().chain(right.iter()).cloned().collect()
             }
-            TypeError::NotInScope(name) => write!(f, "Variable {} is not in scope", name),
-            TypeError::RecursiveType(name, ty) => write!(
-                f,
-                "Type \n    {} = {}\ncontains references to itself",
-                name, ty
-            ),
-            TypeError::DifferentLengths(tys1, tys2) => write!(
-                f,
-                "Cannot match length {} with {} while trying to unify types\n    {:?}\nwith\n    {:?}",
-                tys1.len(),
-                tys2.len(),
-                tys1,
-                tys2
-            ),
+            TypeError::KindMismatch(ty, expected, found) => {
+                vec![ty.clone(), expected.clone(), found.clone()]
+            }
+            TypeError::EscapedSkolem(ty) | TypeError::NotEquatable(ty) => vec![ty.clone()],
+            TypeError::TypeTooDeep(name, _) => vec![named(name)],
+            TypeError::NotInScope(_, _) | TypeError::UnknownLiteral(_) => vec![],
         }
     }
-}
 
-#[derive(Debug, Clone)]
-pub enum TypeConstraint {
-    Match(Type, Type),
-    Unify(Type, Type),
-}
+    // The same error with its variables renamed, including the ones it only knows the name of
+    fn renamed(self: TypeError, renaming: &Substitution) -> TypeError {
+        let rename = |ty: Type| ty.renamed(renaming);
+        let rename_all = |types: Vec<Type>| types.into_iter().map(rename).collect();
+        let rename_name = |name: String| match renaming.get(&name) {
+            Some(Type::Variable(var)) => var.name.clone(),
+            _ => name,
+        };
 
-type TypeResult<T = Type> = Result<T, TypeError>;
+        match self {
+            TypeError::UnificationError(left, right) => {
+                TypeError::UnificationError(rename(left), rename(right))
+            }
+            TypeError::MatchingError(left, right) => {
+                TypeError::MatchingError(rename(left), rename(right))
+            }
+            TypeError::SubstitutionConflict(name, left, right) => {
+                TypeError::SubstitutionConflict(rename_name(name), rename(left), rename(right))
+            }
+            TypeError::RecursiveType(name, ty) => {
+                TypeError::RecursiveType(rename_name(name), rename(ty))
+            }
+            TypeError::DifferentLengths(left, right) => {
+                TypeError::DifferentLengths(rename_all(left), rename_all(right))
+            }
+            TypeError::KindMismatch(ty, expected, found) => {
+                TypeError::KindMismatch(rename(ty), rename(expected), rename(found))
+            }
+            TypeError::EscapedSkolem(ty) => TypeError::EscapedSkolem(rename(ty)),
+            TypeError::NotEquatable(ty) => TypeError::NotEquatable(rename(ty)),
+            TypeError::TypeTooDeep(name, depth) => TypeError::TypeTooDeep(rename_name(name), depth),
+            other @ (TypeError::NotInScope(_, _) | TypeError::UnknownLiteral(_)) => other,
+        }
+    }
 
-type TypeEnv = HashMap<String, Type>;
+    // The same error with the variables the checker made up renamed to a, b, ... the way
+    // types of programs are
+    pub fn normalized(self: &TypeError) -> TypeError {
+        let types = self.types();
+        let renaming = generated_names(&types.iter().collect::<Vec<_>>());
 
-#[derive(Debug, Clone)]
-pub struct TypeContext {
-    environment: TypeEnv,
-    constraints: Vec<TypeConstraint>,
-    next_id: u32,
-}
+        self.clone().renamed(&renaming)
+    }
 
-impl TypeContext {
-    pub fn new() -> TypeContext {
-        TypeContext {
-            environment: {
-                let mut map = TypeEnv::new();
-                map.insert("Type".to_string(), Type::star());
-                map
-            },
-            constraints: Vec::new(),
-            next_id: 0,
+    // What gets filled into the message, in order. Names are printed as they are, so
+    // normalize the error first
+    fn arguments(self: &TypeError) -> Vec<String> {
+        match self {
+            TypeError::UnificationError(t1, t2) | TypeError::MatchingError(t1, t2) => {
+                vec![t1.to_string(), t2.to_string()]
+            }
+            TypeError::KindMismatch(ty, expected, found) => {
+                vec![ty.to_string(), expected.to_string(), found.to_string()]
+            }
+            TypeError::SubstitutionConflict(key, t1, t2) => {
+                vec![key.clone(), t1.to_string(), t2.to_string()]
+            }
+            TypeError::NotInScope(name, _) => vec![name.clone()],
+            TypeError::RecursiveType(name, ty) => {
+                let ty = ty.to_string();
+                let marks = mark_occurrences(name, &ty);
+
+                vec![
+                    name.clone(),
+                    ty,
+                    format!("{}{}", " ".repeat(name.chars().count() + 3), marks),
+                ]
+            }
+            TypeError::DifferentLengths(tys1, tys2) => vec![
+                tys1.len().to_string(),
+                tys2.len().to_string(),
+                format!("{:?}", tys1),
+                format!("{:?}", tys2),
+            ],
+            TypeError::EscapedSkolem(ty) => vec![ty.to_string()],
+            TypeError::UnknownLiteral(text) => vec![text.clone()],
+            TypeError::TypeTooDeep(name, depth) => {
+                vec![name.clone(), depth.to_string(), MAX_TYPE_DEPTH.to_string()]
+            }
+            TypeError::NotEquatable(ty) => vec![ty.to_string()],
         }
     }
 
-    // Create a constraint requiring 2 types to be equal
-    fn should_unify(self: &mut TypeContext, from: &Type, to: &Type) -> () {
-        self.constraints
-            .push(TypeConstraint::Unify(from.clone(), to.clone()))
+    // The message for this error in the given language, without any hints
+    pub fn message(self: &TypeError, locale: Locale) -> String {
+        self.normalized().rendered(locale)
     }
 
-    // Create a constraint requiring 1 type to match another type
-    fn should_match(self: &mut TypeContext, from: &Type, to: &Type) -> () {
-        self.constraints
-            .push(TypeConstraint::Match(from.clone(), to.clone()))
+    fn rendered(self: &TypeError, locale: Locale) -> String {
+        messages::render(locale, self.code(), &self.arguments())
     }
 
-    // Generate a new unique id
-    pub fn get_id(self: &mut TypeContext) -> u32 {
-        let id = self.next_id;
-        self.next_id += 1;
+    // Hints which could help fixing the error
+    pub fn hints(self: &TypeError, locale: Locale) -> Vec<String> {
+        match self {
+            TypeError::NotInScope(_, candidates) if !candidates.is_empty() => {
+                vec![messages::render(
+                    locale,
+                    "did-you-mean",
+                    &[candidates.join(", ")],
+                )]
+            }
+            // Something which takes no arguments got one
+            TypeError::KindMismatch(ty, _, found) if *found == Type::star() => {
+                vec![messages::render(
+                    locale,
+                    "too-many-arguments",
+                    &[ty.head().0.to_string()],
+                )]
+            }
+            // Something which takes arguments is used where a type of values is needed
+            TypeError::KindMismatch(ty, expected, found) if *expected == Type::star() => {
+                let mut missing = 0;
+                let mut kind = found.clone();
+                while let Some((_, result)) = kind.unwrap_function() {
+                    missing += 1;
+                    kind = result;
+                }
 
-        id
+                match missing {
+                    0 => vec![],
+                    1 => vec![messages::render(
+                        locale,
+                        "missing-argument",
+                        &[ty.head().0.to_string()],
+                    )],
+                    _ => vec![messages::render(
+                        locale,
+                        "missing-arguments",
+                        &[ty.head().0.to_string(), missing.to_string()],
+                    )],
+                }
+            }
+            _ => vec![],
+        }
     }
 
-    // Generate a new unique type variable
-    pub fn fresh(self: &mut TypeContext, kind: Type) -> Type {
+    // The message for this error in the given language, followed by its hints
+    pub fn localized(self: &TypeError, locale: Locale) -> String {
+        let error = self.normalized();
+        let mut lines = vec![error.rendered(locale)];
+        lines.extend(error.hints(locale));
+        lines.join("\n")
+    }
+}
+
+// A line with ^ under every place a name appears in some text
+fn mark_occurrences(name: &str, text: &str) -> String {
+    let is_name_char = |character: char| character.is_alphanumeric() || "_'".contains(character);
+    let characters: Vec<char> = text.chars().collect();
+    let length = name.chars().count();
+    let mut marks = String::new();
+    let mut index = 0;
+
+    while index < characters.len() {
+        let candidate: String = characters[index..].iter().take(length).collect();
+        let starts_word = index == 0 || !is_name_char(characters[index - 1]);
+        let ends_word = characters
+            .get(index + length)
+            .is_none_or(|&character| !is_name_char(character));
+
+        if candidate == name && starts_word && ends_word {
+            marks.push_str(&"^".repeat(length));
+            index += length;
+        } else {
+            marks.push(' ');
+            index += 1;
+        }
+    }
+
+    marks.trim_end().to_string()
+}
+
+impl Display for TypeError {
+    fn fmt(self: &TypeError, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.localized(Locale::English))
+    }
+}
+
+// Things worth telling the user about which aren't errors
+#[derive(Debug, Clone)]
+pub enum Note {
+    // A wildcard in an annotation and the type it was inferred to be
+    Wildcard(usize, Type),
+    // A hole in an expression, its type and the bindings which could go there
+    Hole(usize, Option<String>, Type, Vec<(String, Type)>),
+}
+
+impl Note {
+    // Every type the note shows, in the order it shows them
+    pub fn types(self: &Note) -> Vec<&Type> {
+        match self {
+            Note::Wildcard(_, ty) => vec![ty],
+            Note::Hole(_, _, ty, fits) => {
+                let mut types = vec![ty];
+                types.extend(fits.iter().map(|(_, ty)| ty));
+                types
+            }
+        }
+    }
+
+    pub fn map_types(self: Note, f: impl Fn(Type) -> Type) -> Note {
+        match self {
+            Note::Wildcard(offset, ty) => Note::Wildcard(offset, f(ty)),
+            Note::Hole(offset, name, ty, fits) => Note::Hole(
+                offset,
+                name,
+                f(ty),
+                fits.into_iter().map(|(name, ty)| (name, f(ty))).collect(),
+            ),
+        }
+    }
+}
+
+impl Display for Note {
+    fn fmt(self: &Note, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Note::Wildcard(offset, ty) => {
+                write!(f, "Wildcard at offset {} stands for\n    {}", offset, ty)
+            }
+            Note::Hole(offset, name, ty, fits) => {
+                let name = match name {
+                    Some(name) => format!("?{}", name),
+                    None => "_".to_string(),
+                };
+
+                write!(
+                    f,
+                    "Found hole {} at offset {} of type\n    {}",
+                    name, offset, ty
+                )?;
+
+                if !fits.is_empty() {
+                    write!(f, "\nBindings that would fit:")?;
+
+                    for (name, ty) in fits {
+                        write!(f, "\n    {} :: {}", name, ty)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+// Decides the type of a `#...` literal from its text, for literals it knows about
+type LiteralTyper = dyn Fn(&str) -> Option<Type>;
+
+#[derive(Clone)]
+pub struct LiteralHandler(Rc<LiteralTyper>);
+
+impl fmt::Debug for LiteralHandler {
+    fn fmt(self: &Self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "LiteralHandler")
+    }
+}
+
+// An expression left out on purpose, together with what was in scope there
+#[derive(Debug, Clone)]
+pub struct Hole {
+    pub offset: usize,
+    pub name: Option<String>,
+    pub ty: Type,
+    environment: TypeEnv,
+}
+
+impl Substituable for Hole {
+    fn free_variables(self: &Self) -> HashSet<VarName> {
+        self.ty.free_variables()
+    }
+
+    fn apply_substitution(self: Self, substitution: &Substitution) -> Self {
+        Hole {
+            ty: self.ty.apply_substitution(substitution),
+            environment: self.environment.apply_substitution(substitution),
+            ..self
+        }
+    }
+}
+
+// Why a constraint had to hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    IfCondition,
+    IfBranches,
+    Application,
+    Annotation,
+    // What solving a let found out about variables from outside of it
+    Let,
+    Kind,
+}
+
+impl Reason {
+    // Key of the explanation in the message catalog
+    fn key(self: Reason) -> &'static str {
+        match self {
+            Reason::IfCondition => "reason-if-condition",
+            Reason::IfBranches => "reason-if-branches",
+            Reason::Application => "reason-application",
+            Reason::Annotation => "reason-annotation",
+            Reason::Let => "reason-let",
+            Reason::Kind => "reason-kind",
+        }
+    }
+
+    pub fn localized(self: Reason, locale: Locale) -> String {
+        messages::render(locale, self.key(), &[])
+    }
+}
+
+// The expression which produced a constraint, and why
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConstraintOrigin {
+    pub span: Option<Span>,
+    pub reason: Reason,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeConstraint {
+    Match(Type, Type, ConstraintOrigin),
+    Unify(Type, Type, ConstraintOrigin),
+}
+
+impl TypeConstraint {
+    pub fn origin(self: &TypeConstraint) -> ConstraintOrigin {
+        match self {
+            TypeConstraint::Match(_, _, origin) | TypeConstraint::Unify(_, _, origin) => *origin,
+        }
+    }
+
+    // Unification doesn't care about order, so a variable always goes on the left.
+    // Matching does, so those stay as they are
+    pub fn oriented(self: TypeConstraint) -> TypeConstraint {
+        match self {
+            TypeConstraint::Unify(left, right @ Type::Variable(_), origin)
+                if !matches!(left, Type::Variable(_)) =>
+            {
+                TypeConstraint::Unify(right, left, origin)
+            }
+            other => other,
+        }
+    }
+
+    // Whether solving this can't possibly teach us anything
+    pub fn is_trivial(self: &TypeConstraint) -> bool {
+        match self {
+            TypeConstraint::Match(left, right, _) | TypeConstraint::Unify(left, right, _) => {
+                left == right
+            }
+        }
+    }
+}
+
+// Drops the constraints between equal types and the ones seen before, and orients the rest.
+// Inference asks for the same thing over and over, like every use of a variable
+// requiring the same type, so this leaves the solver a lot less to go through.
+// Of two constraints asking for the same thing, the first one is kept together with its origin
+pub fn simplify_constraints(constraints: Vec<TypeConstraint>) -> Vec<TypeConstraint> {
+    let mut seen = std::collections::HashSet::new();
+
+    constraints
+        .into_iter()
+        .map(TypeConstraint::oriented)
+        .filter(|constraint| {
+            if constraint.is_trivial() {
+                return false;
+            }
+
+            match constraint {
+                TypeConstraint::Match(left, right, _) => {
+                    seen.insert((false, left.clone(), right.clone()))
+                }
+                TypeConstraint::Unify(left, right, _) => {
+                    !seen.contains(&(true, right.clone(), left.clone()))
+                        && seen.insert((true, left.clone(), right.clone()))
+                }
+            }
+        })
+        .collect()
+}
+
+// left ~ right for unification and left <~ right for matching, followed by where it came from
+impl Display for TypeConstraint {
+    fn fmt(self: &Self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (left, arrow, right) = match self {
+            TypeConstraint::Match(left, right, _) => (left, "<~", right),
+            TypeConstraint::Unify(left, right, _) => (left, "~", right),
+        };
+        let origin = self.origin();
+
+        write!(f, "{} {} {} -- {:?}", left, arrow, right, origin.reason)?;
+
+        if let Some(span) = origin.span {
+            write!(f, " at offsets {}-{}", span.start, span.end)?;
+        }
+
+        Ok(())
+    }
+}
+
+type TypeResult<T = Type> = Result<T, TypeError>;
+
+// An error together with where it came from
+#[derive(Debug, Clone)]
+pub struct LocatedError {
+    pub error: TypeError,
+    // The expression being inferred when the error showed up, or which produced the constraint
+    pub span: Option<Span>,
+    // Only errors found while solving constraints have one
+    pub reason: Option<Reason>,
+    // The whole types of the constraint which couldn't be solved
+    pub types: Option<(Type, Type)>,
+}
+
+impl LocatedError {
+    // The types of the message and of the notes get renamed together, so a variable has
+    // the same name in all of them
+    pub fn normalized(self: &LocatedError) -> LocatedError {
+        let mut shown = self.error.types();
+        shown.extend(
+            self.shown_types()
+                .into_iter()
+                .flat_map(|(left, right)| vec![left.clone(), right.clone()]),
+        );
+        let renaming = generated_names(&shown.iter().collect::<Vec<_>>());
+
+        LocatedError {
+            error: self.error.clone().renamed(&renaming),
+            types: match self.shown_types() {
+                Some((left, right)) => Some((
+                    left.clone().renamed(&renaming),
+                    right.clone().renamed(&renaming),
+                )),
+                None => self.types.clone(),
+            },
+            ..self.clone()
+        }
+    }
+
+    // Types too deep to work with would only give pages of differences
+    fn shown_types(self: &LocatedError) -> Option<&(Type, Type)> {
+        match self.error {
+            TypeError::TypeTooDeep(_, _) => None,
+            _ => self.types.as_ref(),
+        }
+    }
+
+    // The message for the error in the given language, named like its notes
+    pub fn message(self: &LocatedError, locale: Locale) -> String {
+        self.normalized().error.rendered(locale)
+    }
+
+    // Hints about the error, where inside of the whole types it happened
+    // and why the types had to match, as far as we know
+    pub fn notes(self: &LocatedError, locale: Locale) -> Vec<String> {
+        let located = self.normalized();
+        let mut notes = located.error.hints(locale);
+
+        if let Some((left, right)) = located.shown_types() {
+            for (left, right, context) in left.differences(right) {
+                // Differences making up the whole type are already in the message
+                if context.head().1 > 0 {
+                    let arguments = [left.to_string(), right.to_string(), context.to_string()];
+                    notes.push(messages::render(locale, "type-difference", &arguments));
+                }
+            }
+        }
+
+        notes.extend(self.reason.map(|reason| reason.localized(locale)));
+        notes
+    }
+}
+
+// Persistent, so every closure shares the bindings of the scope it was made in
+// instead of copying all of them
+pub type TypeEnv = im::HashMap<String, Type>;
+
+// How many bindings get suggested for a hole
+const MAX_SUGGESTIONS: usize = 5;
+
+// How deeply nested the types variables get bound to can be. Working on types goes through
+// them recursively, so anything much deeper could overflow the stack
+pub const MAX_TYPE_DEPTH: usize = 2000;
+
+#[derive(Debug, Clone)]
+pub struct TypeContext {
+    environment: TypeEnv,
+    constraints: Vec<TypeConstraint>,
+    next_id: u32,
+    // Offsets of the wildcards we've seen together with the variables standing for them
+    wildcards: Vec<(usize, Type)>,
+    holes: Vec<Hole>,
+    literals: Vec<LiteralHandler>,
+    // Solve constraints as soon as they are generated instead of all at the end
+    eager: bool,
+    // Keep going after errors, collecting them instead of stopping at the first one
+    recover: bool,
+    // Default unknown kinds to * after solving and check every kind again
+    strict_kinds: bool,
+    errors: Vec<LocatedError>,
+    // Span of the innermost located expression being inferred
+    location: Option<Span>,
+    // Everything solved so far in eager mode
+    substitution: Substitution,
+    // How many lets deep we are, and how deep each variable was created.
+    // Variables deeper than the current let can be generalized
+    level: u32,
+    levels: im::HashMap<String, u32>,
+    // Types compared with the builtin equality, which can't end up containing functions,
+    // and where they were compared
+    comparisons: Vec<(Type, Option<Span>)>,
+    // Quantified variables standing for compared types, so their instances are compared too
+    equatable: im::HashSet<String>,
+    generalization: Generalization,
+    // Gets told about every step, when there is one
+    tracer: Option<TraceHandler>,
+    // Collects the tree of unification steps. Shared with child contexts, so it sees all of them
+    recorder: Option<Rc<RefCell<Recorder>>>,
+}
+
+// What TypeContext::snapshot saved
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    environment: TypeEnv,
+    constraints: Vec<TypeConstraint>,
+    next_id: u32,
+    wildcards: Vec<(usize, Type)>,
+    holes: Vec<Hole>,
+    errors: Vec<LocatedError>,
+    location: Option<Span>,
+    substitution: Substitution,
+    level: u32,
+    levels: im::HashMap<String, u32>,
+    comparisons: Vec<(Type, Option<Span>)>,
+    equatable: im::HashSet<String>,
+}
+
+// Which lets get polymorphic types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Generalization {
+    // Every let, like in Haskell
+    #[default]
+    All,
+    // Only lets of syntactic values, like the ML value restriction. Polymorphic
+    // effects or mutable references would otherwise be unsound
+    Values,
+    // No let at all
+    Never,
+}
+
+impl Generalization {
+    // The names used by --generalize
+    pub fn parse(name: &str) -> Option<Generalization> {
+        match name {
+            "all" => Some(Generalization::All),
+            "values" => Some(Generalization::Values),
+            "never" => Some(Generalization::Never),
+            _ => None,
+        }
+    }
+}
+
+// Every setting of a TypeContext in one place, for embedders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeCheckOptions {
+    pub eager_solving: bool,
+    pub error_recovery: bool,
+    pub strict_kinds: bool,
+    pub generalization: Generalization,
+}
+
+// Sets up the environment a TypeContext starts with, for registering host functions
+#[derive(Debug, Clone)]
+pub struct TypeContextBuilder {
+    environment: TypeEnv,
+    literals: Vec<LiteralHandler>,
+    eager: bool,
+}
+
+impl TypeContextBuilder {
+    // Make a name available to programs. Polymorphic bindings should be schemes
+    pub fn with_binding(mut self: TypeContextBuilder, name: &str, ty: Type) -> TypeContextBuilder {
+        self.environment.insert(name.to_string(), ty);
+        self
+    }
+
+    // Give `#...` literals a type. Handlers are tried in the order they were added
+    pub fn with_literal(
+        mut self: TypeContextBuilder,
+        handler: impl Fn(&str) -> Option<Type> + 'static,
+    ) -> TypeContextBuilder {
+        self.literals.push(LiteralHandler(Rc::new(handler)));
+        self
+    }
+
+    // Drop everything added so far, including the prelude
+    pub fn without_prelude(self: TypeContextBuilder) -> TypeContextBuilder {
+        TypeContextBuilder {
+            environment: TypeEnv::new(),
+            ..self
+        }
+    }
+
+    pub fn with_eager_solving(self: TypeContextBuilder) -> TypeContextBuilder {
+        TypeContextBuilder {
+            eager: true,
+            ..self
+        }
+    }
+
+    pub fn build(self: TypeContextBuilder) -> TypeContext {
+        TypeContext {
+            environment: self.environment,
+            literals: self.literals,
+            eager: self.eager,
+            ..TypeContext::empty()
+        }
+    }
+}
+
+impl Default for TypeContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeContext {
+    // A context where only the prelude is in scope
+    pub fn new() -> TypeContext {
+        TypeContext {
+            environment: prelude(),
+            ..TypeContext::empty()
+        }
+    }
+
+    // Start configuring a context, beginning from the prelude
+    pub fn builder() -> TypeContextBuilder {
+        TypeContextBuilder {
+            environment: prelude(),
+            literals: Vec::new(),
+            eager: false,
+        }
+    }
+
+    // A context where nothing at all is in scope
+    pub fn empty() -> TypeContext {
+        TypeContext {
+            environment: TypeEnv::new(),
+            constraints: Vec::new(),
+            next_id: 0,
+            wildcards: Vec::new(),
+            holes: Vec::new(),
+            literals: Vec::new(),
+            eager: false,
+            recover: false,
+            strict_kinds: false,
+            errors: Vec::new(),
+            location: None,
+            substitution: Substitution::new(),
+            level: 1,
+            levels: im::HashMap::new(),
+            comparisons: Vec::new(),
+            equatable: im::HashSet::new(),
+            generalization: Generalization::All,
+            tracer: None,
+            recorder: None,
+        }
+    }
+
+    // Unify types while inferring, which reports errors closer to where they happened
+    pub fn with_eager_solving(self: TypeContext) -> TypeContext {
+        TypeContext {
+            eager: true,
+            ..self
+        }
+    }
+
+    // Collect every error instead of stopping at the first one
+    pub fn with_error_recovery(self: TypeContext) -> TypeContext {
+        TypeContext {
+            recover: true,
+            ..self
+        }
+    }
+
+    // Kinds nothing decided on are usually left alone. This makes them *, so
+    // types which would only kind check thanks to that get rejected
+    pub fn with_strict_kinds(self: TypeContext) -> TypeContext {
+        TypeContext {
+            strict_kinds: true,
+            ..self
+        }
+    }
+
+    pub fn with_generalization(self: TypeContext, generalization: Generalization) -> TypeContext {
+        TypeContext {
+            generalization,
+            ..self
+        }
+    }
+
+    // Call a function on every step the type checker takes. Nothing gets
+    // recorded without one, so tracing costs nothing unless it's asked for
+    pub fn with_tracer(self: TypeContext, tracer: impl Fn(&TraceEvent) + 'static) -> TypeContext {
+        TypeContext {
+            tracer: Some(TraceHandler::new(tracer)),
+            ..self
+        }
+    }
+
+    // Events are only built when someone listens
+    fn trace(self: &TypeContext, event: impl FnOnce() -> TraceEvent) {
+        if let Some(tracer) = &self.tracer {
+            tracer.call(&event())
+        }
+    }
+
+    // Record every unification and matching step, with its inputs and the substitution
+    // it produced. Read them back with solver_trace
+    pub fn with_tracing(self: TypeContext) -> TypeContext {
+        TypeContext {
+            recorder: Some(Rc::new(RefCell::new(Recorder::default()))),
+            ..self
+        }
+    }
+
+    // The outermost steps recorded so far, each holding the ones it needed
+    pub fn solver_trace(self: &TypeContext) -> Vec<TraceStep> {
+        match &self.recorder {
+            Some(recorder) => recorder.borrow().steps().to_vec(),
+            None => Vec::new(),
+        }
+    }
+
+    // Run a step, recording it when tracing
+    fn recorded(
+        self: &mut TypeContext,
+        kind: StepKind,
+        left: Type,
+        right: Type,
+        step: impl FnOnce(&mut TypeContext, Type, Type) -> TypeResult<Substitution>,
+    ) -> TypeResult<Substitution> {
+        let recorder = match &self.recorder {
+            Some(recorder) => recorder.clone(),
+            None => return step(self, left, right),
+        };
+
+        recorder.borrow_mut().begin(kind, &left, &right);
+        let result = step(self, left, right);
+        recorder.borrow_mut().end(&result);
+
+        result
+    }
+
+    // Replaces every setting with the given ones
+    pub fn with_options(self: TypeContext, options: TypeCheckOptions) -> TypeContext {
+        TypeContext {
+            eager: options.eager_solving,
+            recover: options.error_recovery,
+            strict_kinds: options.strict_kinds,
+            generalization: options.generalization,
+            ..self
+        }
+    }
+
+    pub fn options(self: &TypeContext) -> TypeCheckOptions {
+        TypeCheckOptions {
+            eager_solving: self.eager,
+            error_recovery: self.recover,
+            strict_kinds: self.strict_kinds,
+            generalization: self.generalization,
+        }
+    }
+
+    // Fails with the error, unless we're recovering, in which case it gets saved for later
+    fn report(self: &mut TypeContext, error: TypeError) -> TypeResult<()> {
+        let span = self.location;
+        self.report_located(LocatedError {
+            error,
+            span,
+            reason: None,
+            types: None,
+        })
+    }
+
+    fn report_located(self: &mut TypeContext, error: LocatedError) -> TypeResult<()> {
+        if self.recover {
+            self.push_error(error);
+            Ok(())
+        } else {
+            Err(error.error)
+        }
+    }
+
+    // Errors at a span which already has one, or about the same two types as one around
+    // them, are most likely caused by the earlier one. Reporting those would only bury it
+    fn push_error(self: &mut TypeContext, error: LocatedError) {
+        let mismatch = |error: &TypeError| match error {
+            TypeError::UnificationError(left, right) | TypeError::MatchingError(left, right) => {
+                Some((left.clone(), right.clone()))
+            }
+            _ => None,
+        };
+        let overlap = |left: Option<Span>, right: Option<Span>| match (left, right) {
+            (Some(left), Some(right)) => left.start < right.end && right.start < left.end,
+            _ => true,
+        };
+
+        let follows = self.errors.iter().any(|reported| {
+            let same_span = reported.span.is_some() && reported.span == error.span;
+            let same_types = match (mismatch(&reported.error), mismatch(&error.error)) {
+                (Some((l1, r1)), Some((l2, r2))) => {
+                    (l1 == l2 && r1 == r2) || (l1 == r2 && r1 == l2)
+                }
+                _ => false,
+            };
+
+            same_span || (same_types && overlap(reported.span, error.span))
+        });
+
+        if !follows {
+            self.errors.push(error);
+        }
+    }
+
+    // The errors collected while recovering
+    pub fn errors(self: &TypeContext) -> &[LocatedError] {
+        &self.errors
+    }
+
+    // Constraints generated but not solved yet. In eager mode only deferred ones wait here
+    pub fn pending_constraints(self: &TypeContext) -> &[TypeConstraint] {
+        &self.constraints
+    }
+
+    // What eager solving has found out so far. Lazy contexts return it from solve_constraints
+    pub fn current_substitution(self: &TypeContext) -> &Substitution {
+        &self.substitution
+    }
+
+    // Every binding in scope, in no particular order
+    pub fn environment_iter(self: &TypeContext) -> impl Iterator<Item = (&String, &Type)> {
+        self.environment.iter()
+    }
+
+    // Remember the expression being inferred, for when the constraint can't be solved
+    fn origin(self: &TypeContext, reason: Reason) -> ConstraintOrigin {
+        ConstraintOrigin {
+            span: self.location,
+            reason,
+        }
+    }
+
+    // Create a constraint requiring 2 types to be equal
+    fn should_unify(
+        self: &mut TypeContext,
+        from: &Type,
+        to: &Type,
+        reason: Reason,
+    ) -> TypeResult<()> {
+        let origin = self.origin(reason);
+        self.add_constraint(TypeConstraint::Unify(from.clone(), to.clone(), origin))
+    }
+
+    fn add_constraint(self: &mut TypeContext, constraint: TypeConstraint) -> TypeResult<()> {
+        self.trace(|| TraceEvent::Constraint(constraint.clone()));
+
+        if self.eager {
+            let constraint = constraint.apply_substitution(&self.substitution);
+            let subst = self.solve_constraints_with_subst(&[constraint], Substitution::new())?;
+
+            self.substitution = merge_substitutions(subst, self.substitution.clone());
+        } else {
+            self.constraints.push(constraint);
+        }
+
+        Ok(())
+    }
+
+    // Constraints found while solving other constraints always wait for the solver
+    fn defer_unify(self: &mut TypeContext, from: &Type, to: &Type, reason: Reason) {
+        let origin = self.origin(reason);
+        let constraint = TypeConstraint::Unify(from.clone(), to.clone(), origin);
+
+        self.trace(|| TraceEvent::Constraint(constraint.clone()));
+        self.constraints.push(constraint)
+    }
+
+    // Generate a new unique id
+    pub fn get_id(self: &mut TypeContext) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        id
+    }
+
+    // Generate a new unique type variable
+    pub fn fresh(self: &mut TypeContext, kind: Type) -> Type {
         let id = self.get_id();
         let name = format!("t{}", id);
+        self.levels.insert(name.clone(), self.level);
+        Type::Variable(VarName {
+            kind: Rc::new(kind),
+            name,
+        })
+    }
+
+    // Generate a new type variable standing for something with a runtime value
+    pub fn fresh_star(self: &mut TypeContext) -> Type {
+        self.fresh(Type::star())
+    }
+
+    // Generate a new variable standing for a kind we don't know yet
+    pub fn fresh_kind(self: &mut TypeContext) -> Type {
+        let id = self.get_id();
+        let name = format!("k{}", id);
+        self.levels.insert(name.clone(), self.level);
         Type::Variable(VarName {
-            kind: Box::new(kind),
+            kind: Rc::new(Type::NoKind),
             name,
         })
     }
 
+    // Generate a new type variable, inventing a kind for it if it doesn't have one
+    fn fresh_kinded(self: &mut TypeContext, kind: Type) -> Type {
+        if kind == Type::NoKind {
+            let kind = self.fresh_kind();
+            self.fresh(kind)
+        } else {
+            self.fresh(kind)
+        }
+    }
+
+    // Replace all quantifiers with rigid variables
+    pub fn skolemize(self: &mut TypeContext, ty: &Type) -> Type {
+        match ty {
+            Type::Scheme { variables, ty } => {
+                let mut substitution = Substitution::new();
+
+                for var in variables {
+                    let skolem = Type::skolem(var, self.get_id());
+                    substitution.insert(var.name.clone(), skolem);
+                }
+
+                ty.as_ref().clone().apply_substitution(&substitution)
+            }
+            other => other.clone(),
+        }
+    }
+
     // Replace all quantifiers with fresh variables
     pub fn instantiate(self: &mut TypeContext, ty: &Type) -> Type {
         match ty {
             Type::Scheme { variables, ty } => {
-                let new_variables = variables
-                    .into_iter()
-                    .map(|var| (var.name.clone(), self.fresh(*var.kind.clone())));
-                let substitution = new_variables.collect();
+                // Quantified variables which show up in the kinds of others are kind variables.
+                // Most schemes only quantify over types of known kinds, so we skip looking
+                let kind_variables: HashSet<String> =
+                    if variables.iter().all(|var| var.kind.is_closed()) {
+                        HashSet::new()
+                    } else {
+                        variables
+                            .iter()
+                            .flat_map(|var| var.kind.free_variables())
+                            .map(|var| var.name)
+                            .collect()
+                    };
+
+                let mut substitution = Substitution::new();
+
+                for var in variables {
+                    if kind_variables.contains(&var.name) {
+                        substitution.insert(var.name.clone(), self.fresh_kind());
+                    }
+                }
+
+                // The kinds have to be refreshed too, or every instance would share them
+                for var in variables {
+                    if !kind_variables.contains(&var.name) {
+                        let kind = var.kind.as_ref().clone().apply_substitution(&substitution);
+                        let fresh = self.fresh_kinded(kind);
+                        substitution.insert(var.name.clone(), fresh);
+                    }
+                }
+
+                for var in variables {
+                    if self.equatable.contains(&var.name) {
+                        let compared = substitution[&var.name].clone();
+                        self.comparisons.push((compared, self.location));
+                    }
+                }
 
-                ty.clone().apply_substitution(&substitution)
+                ty.as_ref().clone().apply_substitution(&substitution)
             }
             other => other.clone(),
         }
     }
 
+    // Solve constraints one after the other in a loop, so long programs can't overflow
+    // the stack. Each one only gets the substitution applied once it's reached
     fn solve_constraints_with_subst(
         self: &mut TypeContext,
-        constraints: &Vec<TypeConstraint>,
-        substitution: Substitution,
+        constraints: &[TypeConstraint],
+        mut substitution: Substitution,
     ) -> TypeResult<Substitution> {
-        match &constraints[..] {
-            [] => Ok(substitution),
-            [constraint, ..] => {
-                let new_subst = match constraint {
-                    TypeConstraint::Unify(left, right) => {
-                        let left = self.kind_unkinded(left.clone());
-                        let right = self.kind_unkinded(right.clone());
-                        merge_substitutions(self.unify(left, right)?, substitution)
-                    }
-                    TypeConstraint::Match(left, right) => {
-                        let left = self.kind_unkinded(left.clone());
-                        let right = self.kind_unkinded(right.clone());
-                        merge_substitutions(self.match_types(left, right)?, substitution)
-                    }
-                };
-                let constraints = constraints[1..].to_vec().apply_substitution(&new_subst);
-                self.solve_constraints_with_subst(&constraints, new_subst)
+        for constraint in constraints {
+            let constraint = constraint.clone().apply_substitution(&substitution);
+            let solved = match &constraint {
+                TypeConstraint::Unify(left, right, _) => self.unify(left.clone(), right.clone()),
+                TypeConstraint::Match(left, right, _) => {
+                    self.match_types(left.clone(), right.clone())
+                }
+            };
+
+            // When recovering, a constraint which can't be solved is reported and skipped
+            match solved {
+                Ok(solved) => {
+                    self.trace(|| TraceEvent::Merge(solved.clone()));
+                    substitution = merge_substitutions(solved, substitution);
+                }
+                Err(error) => {
+                    let (left, right, origin) = match constraint {
+                        TypeConstraint::Unify(left, right, origin)
+                        | TypeConstraint::Match(left, right, origin) => (left, right, origin),
+                    };
+                    self.report_located(LocatedError {
+                        error,
+                        span: origin.span,
+                        reason: Some(origin.reason),
+                        types: Some((left, right)),
+                    })?;
+                }
             }
         }
+
+        Ok(substitution)
     }
 
     pub fn solve_constraints(self: &mut TypeContext) -> TypeResult<Substitution> {
-        let initial_constraints = self.constraints.clone();
+        // In eager mode whatever is left can still mention variables solved since
+        self.constraints = self
+            .constraints
+            .clone()
+            .apply_substitution(&self.substitution);
 
-        self.constraints = vec![];
+        let subst = merge_substitutions(self.solve_pending()?, self.substitution.clone());
 
-        let subst = self.solve_constraints_with_subst(&initial_constraints, Substitution::new())?;
+        if self.eager {
+            self.substitution = subst.clone();
+        }
 
-        if self.constraints.len() > 0 {
-            println!("Found more constraints, continuing to solve");
-            self.constraints = self.constraints.clone().apply_substitution(&subst);
-            let subst2 = self.solve_constraints()?;
+        Ok(subst)
+    }
 
-            Ok(merge_substitutions(subst2, subst))
-        } else {
-            Ok(subst)
+    fn solve_pending(self: &mut TypeContext) -> TypeResult<Substitution> {
+        let mut substitution = Substitution::new();
+
+        // Solving can find new constraints (on kinds), which get solved in another round
+        while !self.constraints.is_empty() {
+            let constraints = simplify_constraints(mem::take(&mut self.constraints));
+            substitution = self.solve_constraints_with_subst(&constraints, substitution)?;
+        }
+
+        Ok(substitution)
+    }
+
+    // A context seeing everything this one does, but which collects its own constraints and
+    // wildcards. Only what it collects gets copied back by sync, so nothing is duplicated.
+    // Fields are copied one by one, since cloning the whole context would copy what
+    // this one collected only to throw it away
+    fn child(self: &TypeContext) -> TypeContext {
+        TypeContext {
+            environment: self.environment.clone(),
+            constraints: Vec::new(),
+            next_id: self.next_id,
+            wildcards: Vec::new(),
+            holes: Vec::new(),
+            literals: self.literals.clone(),
+            eager: self.eager,
+            recover: self.recover,
+            strict_kinds: self.strict_kinds,
+            errors: Vec::new(),
+            location: self.location,
+            substitution: self.substitution.clone(),
+            level: self.level,
+            levels: self.levels.clone(),
+            comparisons: Vec::new(),
+            equatable: self.equatable.clone(),
+            generalization: self.generalization,
+            tracer: self.tracer.clone(),
+            recorder: self.recorder.clone(),
         }
     }
 
     // Create a new context based on a new variable
     pub fn create_closure(self: &TypeContext, name: String, scheme: Type) -> TypeContext {
-        let mut context = self.clone();
+        let mut context = self.child();
 
         context.environment.insert(name, scheme);
 
@@ -366,101 +1922,650 @@ impl TypeContext {
     }
 
     // Infer a expression inside a closure
-    pub fn infer_with(self: &mut TypeContext, name: String, scheme: Type, ast: Ast) -> TypeResult {
-        let mut new_ctx = self.create_closure(name, scheme);
-        let result = new_ctx.infer(ast);
+    pub fn infer_with(
+        self: &mut TypeContext,
+        name: String,
+        scheme: Type,
+        ast: Ast,
+    ) -> TypeResult<TypedAst> {
+        self.in_scope(name, scheme, |context| context.infer(ast))
+    }
+
+    // Run something with one more binding in scope. Only the environment gets put back
+    // afterwards, everything else it found out stays. The environment is persistent,
+    // so keeping the old one around is cheap
+    fn in_scope<T>(
+        self: &mut TypeContext,
+        name: String,
+        scheme: Type,
+        run: impl FnOnce(&mut TypeContext) -> T,
+    ) -> T {
+        let outer = self.environment.clone();
+        self.environment.insert(name, scheme);
 
-        self.sync(new_ctx);
+        let result = run(self);
 
+        self.environment = outer;
         result
     }
 
+    // Save everything checking an expression could change, so it can be undone with
+    // rollback. Embedders keeping a context around, like a REPL or an editor, can try out
+    // an expression without keeping anything it did when it doesn't check
+    pub fn snapshot(self: &TypeContext) -> Snapshot {
+        Snapshot {
+            environment: self.environment.clone(),
+            constraints: self.constraints.clone(),
+            next_id: self.next_id,
+            wildcards: self.wildcards.clone(),
+            holes: self.holes.clone(),
+            errors: self.errors.clone(),
+            location: self.location,
+            substitution: self.substitution.clone(),
+            level: self.level,
+            levels: self.levels.clone(),
+            comparisons: self.comparisons.clone(),
+            equatable: self.equatable.clone(),
+        }
+    }
+
+    // Go back to how things were when the snapshot was taken
+    pub fn rollback(self: &mut TypeContext, snapshot: Snapshot) {
+        self.environment = snapshot.environment;
+        self.constraints = snapshot.constraints;
+        self.next_id = snapshot.next_id;
+        self.wildcards = snapshot.wildcards;
+        self.holes = snapshot.holes;
+        self.errors = snapshot.errors;
+        self.location = snapshot.location;
+        self.substitution = snapshot.substitution;
+        self.level = snapshot.level;
+        self.levels = snapshot.levels;
+        self.comparisons = snapshot.comparisons;
+        self.equatable = snapshot.equatable;
+    }
+
     // copy stuff over from another context
-    pub fn sync(self: &mut TypeContext, other: TypeContext) -> () {
+    pub fn sync(self: &mut TypeContext, other: TypeContext) {
         self.constraints.extend(other.constraints);
+        self.wildcards.extend(other.wildcards);
+        self.holes.extend(other.holes);
+        for error in other.errors {
+            self.push_error(error);
+        }
         self.next_id = max(other.next_id, self.next_id);
+        self.levels = other.levels;
+        self.comparisons.extend(other.comparisons);
+        self.equatable = other.equatable;
+
+        // The other context started out with our substitution and could only extend it
+        if self.eager {
+            self.substitution = other.substitution;
+        }
     }
 
     // Infer the type of an expression
-    pub fn infer(self: &mut TypeContext, expression: Ast) -> TypeResult {
+    pub fn infer(self: &mut TypeContext, expression: Ast) -> TypeResult<TypedAst> {
         match expression {
-            Ast::FloatLiteral(_) => Ok(Type::number()),
-            Ast::StringLiteral(_) => Ok(Type::string()),
+            Ast::FloatLiteral(value) => Ok(TypedAst::new(
+                TypedNode::FloatLiteral(value),
+                Type::number(),
+            )),
+            Ast::StringLiteral(value) => Ok(TypedAst::new(
+                TypedNode::StringLiteral(value),
+                Type::string(),
+            )),
+            Ast::CharLiteral(value) => {
+                Ok(TypedAst::new(TypedNode::CharLiteral(value), Type::char()))
+            }
+            Ast::CustomLiteral(text) => {
+                let scheme = self
+                    .literals
+                    .iter()
+                    .find_map(|LiteralHandler(handler)| handler(&text));
+
+                let ty = match scheme {
+                    Some(scheme) => self.instantiate(&scheme),
+                    None => {
+                        self.report(TypeError::UnknownLiteral(text.clone()))?;
+                        Type::Error
+                    }
+                };
+                Ok(TypedAst::new(TypedNode::CustomLiteral(text), ty))
+            }
             Ast::Annotation(annotated, annotation) => {
-                let inferred = self.infer(*annotated)?;
+                // Kinds get solved before anything uses the annotation, so that skolems and
+                // instances of variables like the f in f a know they take an argument
+                let annotation = self.kind_unkinded(annotation);
+                let annotation = match self.infer_kinds(annotation) {
+                    Ok(annotation) => annotation,
+                    Err(error) => {
+                        self.report(error)?;
+                        Type::Error
+                    }
+                };
+
+                let mut wildcards: Vec<_> = annotation
+                    .free_variables()
+                    .into_iter()
+                    .filter(VarName::is_wildcard)
+                    .map(|var| (var.name[1..].parse().unwrap(), Type::Variable(var)))
+                    .collect();
+                wildcards.sort_by_key(|(offset, _)| *offset);
+
+                // Wildcards are new variables as well, just named by the parser
+                for (_, wildcard) in &wildcards {
+                    if let Type::Variable(var) = wildcard {
+                        self.levels.insert(var.name.clone(), self.level);
+                    }
+                }
+                self.wildcards.extend(wildcards);
+
+                // The expression has to be at least as general as the annotation,
+                // and can then be used at any of its instances
+                let scheme = annotation.quantify_annotation();
+                let skolemized = self.skolemize(&scheme);
+                let inferred = self.check_against(*annotated, &skolemized, Reason::Annotation)?;
+
+                let ty = self.instantiate(&scheme);
+                Ok(TypedAst::new(
+                    TypedNode::Annotation(Box::new(inferred), skolemized),
+                    ty,
+                ))
+            }
+            Ast::Foreign(name, ty, body) => {
+                let ty = self.kind_unkinded(ty);
+                let ty = match self.infer_kinds(ty) {
+                    Ok(ty) => ty,
+                    Err(error) => {
+                        self.report(error)?;
+                        Type::Error
+                    }
+                };
+
+                // The host has to work for every type, so free variables are quantified
+                let scheme = if ty.is_scheme() {
+                    ty
+                } else {
+                    let variables = ty.free_variables().into_iter().collect();
+                    ty.to_scheme(variables)
+                };
 
-                self.should_match(&inferred, &annotation);
+                let typed_body = self.infer_with(name.clone(), scheme.clone(), *body)?;
 
-                Ok(inferred)
+                let ty = typed_body.ty.clone();
+                Ok(TypedAst::new(
+                    TypedNode::Foreign(name, scheme, Box::new(typed_body)),
+                    ty,
+                ))
             }
             Ast::If(condition, right, left) => {
-                let type_condition = self.infer(*condition)?;
-                let type_right = self.infer(*right)?;
-                let type_left = self.infer(*left)?;
-                self.should_unify(&type_condition, &Type::boolean());
-                self.should_unify(&type_left, &type_right);
-
-                Ok(type_right)
+                let typed_condition =
+                    self.check_against(*condition, &Type::boolean(), Reason::IfCondition)?;
+                let typed_right = self.infer(*right)?;
+                let typed_left = self.check_against(*left, &typed_right.ty, Reason::IfBranches)?;
+
+                let ty = typed_right.ty.clone();
+                Ok(TypedAst::new(
+                    TypedNode::If(
+                        Box::new(typed_condition),
+                        Box::new(typed_right),
+                        Box::new(typed_left),
+                    ),
+                    ty,
+                ))
             }
-            Ast::Variable(name) => match self.environment.clone().get(&name) {
-                Some(result) => Ok(self.instantiate(result)),
-                None => Err(TypeError::NotInScope(name)),
+            // Only the scheme gets copied, since instantiating needs the context mutably
+            Ast::Variable(name) => match self.environment.get(&name).cloned() {
+                Some(scheme) => {
+                    let ty = self.instantiate(&scheme);
+
+                    // Only the builtin comparisons are restricted, hosts and programs can
+                    // bind eq to whatever they want
+                    if prelude::EQUALITY.contains(&name.as_str()) && scheme == prelude::equality() {
+                        if let Some((compared, _)) = ty.unwrap_function() {
+                            self.comparisons.push((compared, self.location));
+                        }
+                    }
+
+                    Ok(TypedAst::new(TypedNode::Variable(name), ty))
+                }
+                None => {
+                    let candidates = self.similar_names(&name);
+                    self.report(TypeError::NotInScope(name.clone(), candidates))?;
+
+                    Ok(TypedAst::new(TypedNode::Variable(name), Type::Error))
+                }
             },
             Ast::FunctionCall(function, argument) => {
-                let return_type = self.fresh(Type::NoKind);
-                let function_type = self.infer(*function)?;
-                let argument_type = self.infer(*argument)?;
-
-                self.should_unify(
-                    &function_type,
-                    &Type::create_lambda(argument_type, return_type.clone()),
-                );
+                let typed_function = self.infer(*function)?;
+                let function_ty = typed_function
+                    .ty
+                    .clone()
+                    .apply_substitution(&self.substitution);
+
+                let (typed_argument, return_type) = match function_ty.unwrap_function() {
+                    // Functions taking polymorphic arguments come from annotations
+                    Some((from, to)) if from.is_scheme() => {
+                        (self.check_polymorphic(*argument, &from)?, to)
+                    }
+                    // When the function is known the argument can be checked against it
+                    Some((from, to)) => (
+                        self.check_against(*argument, &from, Reason::Application)?,
+                        to,
+                    ),
+                    None => {
+                        let return_type = self.fresh_star();
+                        let typed_argument = self.infer(*argument)?;
+
+                        self.should_unify(
+                            &typed_function.ty,
+                            &Type::create_lambda(typed_argument.ty.clone(), return_type.clone()),
+                            Reason::Application,
+                        )?;
+
+                        (typed_argument, return_type)
+                    }
+                };
 
-                Ok(return_type)
+                Ok(TypedAst::new(
+                    TypedNode::FunctionCall(Box::new(typed_function), Box::new(typed_argument)),
+                    return_type,
+                ))
             }
             Ast::Lambda(argument, body) => {
-                let arg_type = self.fresh(Type::NoKind);
-                let return_type = self.infer_with(argument, arg_type.clone(), *body)?;
+                let arg_type = self.fresh_star();
+                let typed_body = self.infer_with(argument.clone(), arg_type.clone(), *body)?;
 
-                Ok(Type::create_lambda(arg_type, return_type))
+                let ty = Type::create_lambda(arg_type, typed_body.ty.clone());
+                Ok(TypedAst::new(
+                    TypedNode::Lambda(argument, Box::new(typed_body)),
+                    ty,
+                ))
+            }
+            // Comments are only kept around for the formatter
+            Ast::Commented(_, expression) => self.infer(*expression),
+            // The parser already complained about this
+            Ast::Error(text) => Ok(TypedAst::new(TypedNode::Error(text), Type::Error)),
+            Ast::Hole(offset, name) => {
+                let ty = self.fresh_star();
+
+                self.holes.push(Hole {
+                    offset,
+                    name: name.clone(),
+                    ty: ty.clone(),
+                    environment: self.environment.clone(),
+                });
+
+                Ok(TypedAst::new(TypedNode::Hole(name), ty))
+            }
+            Ast::Do(statements, result) => self.infer(Ast::desugar_do(statements, *result)),
+            Ast::Operator(operator, left, right) => {
+                self.infer(Ast::desugar_operator(operator, *left, *right))
+            }
+            Ast::MultiWayIf(branches, otherwise) => {
+                self.infer(Ast::desugar_multi_way_if(branches, *otherwise))
+            }
+            Ast::Negation(inner) => {
+                self.infer(Ast::Variable("negate".to_string()).call_chain(vec![*inner]))
+            }
+            Ast::Located(span, expression) => {
+                let outer = self.location.replace(span);
+                let typed = self.infer(*expression);
+                self.location = outer;
+                let typed = typed?;
+
+                let ty = typed.ty.clone();
+                self.trace(|| TraceEvent::Typed(span, ty.clone()));
+                Ok(TypedAst::new(TypedNode::Located(span, Box::new(typed)), ty))
             }
             Ast::Let(name, value, body) => {
-                let mut value_ctx = self.clone();
-                value_ctx.constraints = Vec::new();
-                let value_type = value_ctx.infer(*value)?;
-                let substitution = value_ctx.solve_constraints()?;
+                let (typed_value, scheme) = self.infer_generalized(*value)?;
+                let typed_body = self.infer_with(name.clone(), scheme, *body)?;
+
+                let ty = typed_body.ty.clone();
+                Ok(TypedAst::new(
+                    TypedNode::Let(name, Box::new(typed_value), Box::new(typed_body)),
+                    ty,
+                ))
+            }
+        }
+    }
+
+    // Check an expression against the type it is expected to have, the other half of infer.
+    // The expected type gets pushed down to where it matters, so mismatches are reported
+    // at the expression causing them. Lambdas take their argument types from it, which
+    // can be polymorphic, so those get used as they are instead of being instantiated
+    fn check_against(
+        self: &mut TypeContext,
+        expression: Ast,
+        expected: &Type,
+        reason: Reason,
+    ) -> TypeResult<TypedAst> {
+        let known = expected.clone().apply_substitution(&self.substitution);
+
+        match (expression, known.unwrap_function()) {
+            (Ast::Lambda(argument, body), Some((from, to))) => {
+                let typed_body = self.in_scope(argument.clone(), from.clone(), |context| {
+                    context.check_against(*body, &to, reason)
+                })?;
+
+                let ty = Type::create_lambda(from, typed_body.ty.clone());
+                Ok(TypedAst::new(
+                    TypedNode::Lambda(argument, Box::new(typed_body)),
+                    ty,
+                ))
+            }
+            (Ast::Located(span, expression), _) => {
+                let outer = self.location.replace(span);
+                let typed = self.check_against(*expression, expected, reason);
+                self.location = outer;
+                let typed = typed?;
+
+                let ty = typed.ty.clone();
+                self.trace(|| TraceEvent::Typed(span, ty.clone()));
+                Ok(TypedAst::new(TypedNode::Located(span, Box::new(typed)), ty))
+            }
+            (Ast::Commented(_, expression), _) => self.check_against(*expression, expected, reason),
+            (Ast::If(condition, right, left), _) => {
+                let typed_condition =
+                    self.check_against(*condition, &Type::boolean(), Reason::IfCondition)?;
+                let typed_right = self.check_against(*right, expected, reason)?;
+                let typed_left = self.check_against(*left, expected, reason)?;
+
+                Ok(TypedAst::new(
+                    TypedNode::If(
+                        Box::new(typed_condition),
+                        Box::new(typed_right),
+                        Box::new(typed_left),
+                    ),
+                    expected.clone(),
+                ))
+            }
+            (Ast::Let(name, value, body), _) => {
+                let (typed_value, scheme) = self.infer_generalized(*value)?;
+                let typed_body = self.in_scope(name.clone(), scheme, |context| {
+                    context.check_against(*body, expected, reason)
+                })?;
+
+                let ty = typed_body.ty.clone();
+                Ok(TypedAst::new(
+                    TypedNode::Let(name, Box::new(typed_value), Box::new(typed_body)),
+                    ty,
+                ))
+            }
+            (Ast::Do(statements, result), _) => {
+                self.check_against(Ast::desugar_do(statements, *result), expected, reason)
+            }
+            (Ast::MultiWayIf(branches, otherwise), _) => self.check_against(
+                Ast::desugar_multi_way_if(branches, *otherwise),
+                expected,
+                reason,
+            ),
+            (expression, _) => {
+                let typed = self.infer(expression)?;
+                self.should_unify(&typed.ty, expected, reason)?;
+
+                Ok(typed)
+            }
+        }
+    }
+
+    // An argument passed where a polymorphic one is expected has to work for every
+    // instance, so it gets checked like it was annotated with the expected type
+    fn check_polymorphic(
+        self: &mut TypeContext,
+        argument: Ast,
+        expected: &Type,
+    ) -> TypeResult<TypedAst> {
+        let skolemized = self.skolemize(expected);
+        let typed = self.check_against(argument, &skolemized, Reason::Annotation)?;
+
+        Ok(TypedAst::new(
+            TypedNode::Annotation(Box::new(typed), skolemized),
+            expected.clone(),
+        ))
+    }
+
+    // Infer the value of a let and generalize it. The value's constraints are solved
+    // right away and then dropped, so they never reach this context. What solving them
+    // found out about variables from outside the let comes back as equalities instead,
+    // while the ones only the value knows about stay behind
+    fn infer_generalized(self: &mut TypeContext, value: Ast) -> TypeResult<(TypedAst, Type)> {
+        let generalize = match self.generalization {
+            Generalization::All => true,
+            Generalization::Values => value.is_value(),
+            Generalization::Never => false,
+        };
+
+        let mut value_ctx = self.child();
+        value_ctx.level += 1;
+        let typed_value = value_ctx.infer(value)?;
+        let substitution = value_ctx.solve_constraints()?;
+
+        let typed_value = typed_value.apply_substitution(&substitution);
+        value_ctx.wildcards = value_ctx
+            .wildcards
+            .into_iter()
+            .map(|(offset, ty)| (offset, ty.apply_substitution(&substitution)))
+            .collect();
+        value_ctx.holes = value_ctx.holes.apply_substitution(&substitution);
+        value_ctx.comparisons = value_ctx
+            .comparisons
+            .into_iter()
+            .map(|(ty, span)| (ty.apply_substitution(&substitution), span))
+            .collect();
+        let compared: HashSet<String> = value_ctx
+            .comparisons
+            .iter()
+            .flat_map(|(ty, _)| ty.free_variables())
+            .map(|var| var.name)
+            .collect();
+
+        self.sync(value_ctx);
+        self.lower_levels(&substitution);
+
+        let scheme = if generalize {
+            let scheme = typed_value.ty.generalize(self);
+
+            // Every use of the let compares whatever these get instantiated to
+            if let Type::Scheme { variables, .. } = &scheme {
+                for var in variables {
+                    if compared.contains(&var.name) {
+                        self.equatable.insert(var.name.clone());
+                    }
+                }
+            }
+
+            scheme
+        } else {
+            // The variables stay shared by every use, so later lets mustn't generalize them either
+            self.lower_levels_in(&typed_value.ty, self.level);
+            typed_value.ty.clone()
+        };
+
+        // Variables created inside the value have been substituted away already, so only the
+        // ones reachable from outside of the let are worth telling the outer solver about
+        for (variable, ty) in substitution {
+            if self.level_of(&variable) <= self.level {
+                self.defer_unify(&Type::from_string(variable), &ty, Reason::Let);
+            }
+        }
+
+        Ok((typed_value, scheme))
+    }
+
+    // Names in scope which are a few typos away from the given one, closest first
+    fn similar_names(self: &TypeContext, name: &str) -> Vec<String> {
+        // Short names are all close to each other, so allow fewer typos for those
+        let max_distance = max(1, name.chars().count() / 3);
+
+        let mut candidates: Vec<_> = self
+            .environment
+            .keys()
+            // Whatever got bound to _ can't be referred to
+            .filter(|candidate| candidate.as_str() != "_")
+            .map(|candidate| (edit_distance(name, candidate), candidate.clone()))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect();
+
+        candidates.sort();
+
+        candidates
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, candidate)| candidate)
+            .collect()
+    }
+
+    // The substitution unifying 2 types would produce, without committing to it
+    pub fn unifier(self: &TypeContext, left: &Type, right: &Type) -> Option<Substitution> {
+        // Trying things out isn't a step worth tracing
+        let mut context = TypeContext {
+            tracer: None,
+            recorder: None,
+            ..self.child()
+        };
+
+        context.unify(left.clone(), right.clone()).ok()
+    }
+
+    // Check if 2 types could be unified, leaving this context untouched
+    pub fn can_unify(self: &TypeContext, left: &Type, right: &Type) -> bool {
+        self.unifier(left, right).is_some()
+    }
+
+    // The bindings from an environment which could be used where the expected type is needed,
+    // best first. The less a binding needs to be specialized, the better it fits
+    fn fitting_bindings(
+        self: &TypeContext,
+        environment: &TypeEnv,
+        expected: &Type,
+    ) -> Vec<(String, Type)> {
+        // Anything fits a hole nothing is known about, so listing everything wouldn't help
+        if let Type::Variable(_) = expected {
+            return Vec::new();
+        }
 
-                self.with_substitution(&substitution);
+        let mut context = self.clone();
+        let mut fits: Vec<_> = environment
+            .iter()
+            // Whatever got bound to _ can't be referred to
+            .filter(|(name, _)| name.as_str() != "_")
+            .filter_map(|(name, ty)| {
+                let instance = context.instantiate(ty);
+                let unifier = context.unifier(&instance, expected)?;
+
+                Some((unifier.len(), name.clone(), ty.clone()))
+            })
+            .collect();
+
+        fits.sort_by(|(left_size, left, _), (right_size, right, _)| {
+            left_size.cmp(right_size).then(left.cmp(right))
+        });
+
+        fits.into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, name, ty)| (name, ty))
+            .collect()
+    }
+
+    // The let level a variable was created at. Ones we didn't create came from outside,
+    // like the environment given by the host, so they are never generalized
+    pub fn level_of(self: &TypeContext, name: &str) -> u32 {
+        self.levels.get(name).copied().unwrap_or(0)
+    }
+
+    // Solving a let can tie variables from outside of it to ones created inside.
+    // Those become reachable from the environment, so they take the outer level
+    fn lower_levels(self: &mut TypeContext, substitution: &Substitution) {
+        for (name, ty) in substitution {
+            let level = self.level_of(name);
 
-                let scheme = value_type
-                    .apply_substitution(&substitution)
-                    .generalize(self);
+            if level <= self.level {
+                self.lower_levels_in(ty, level);
+            }
+        }
+    }
 
-                self.sync(value_ctx);
-                self.infer_with(name, scheme, *body)
+    // Move every variable of a type, and their kinds, up to some level
+    fn lower_levels_in(self: &mut TypeContext, ty: &Type, level: u32) {
+        for variable in ty.free_variables() {
+            for name in variable
+                .kind
+                .free_variables()
+                .into_iter()
+                .map(|kind| kind.name)
+                .chain(Some(variable.name))
+            {
+                if self.level_of(&name) > level {
+                    self.levels.insert(name, level);
+                }
             }
         }
     }
 
+    // Names of the type variables free somewhere in the environment.
+    // Those stand for types which aren't known yet, so they must not be generalized
+    pub fn environment_free_variables(self: &TypeContext) -> HashSet<String> {
+        self.environment
+            .free_variables()
+            .into_iter()
+            .map(|variable| variable.name)
+            .collect()
+    }
+
     // Applies a substitution on the current environment
-    pub fn with_substitution(self: &mut Self, substitution: &Substitution) -> () {
-        self.environment = self.environment.clone().apply_substitution(substitution);
+    pub fn with_substitution(self: &mut Self, substitution: &Substitution) {
+        let environment = mem::take(&mut self.environment);
+        self.environment = environment.apply_substitution(substitution);
     }
 
-    pub fn constrain_type_application(self: &mut Self, func: Type, input: Type) -> (Type, Type) {
-        let k_ret = self.fresh(Type::NoKind);
-        let k_fun = self.get_kind(func);
-        let k_input = self.get_kind(input);
+    pub fn constrain_type_application(
+        self: &mut Self,
+        func: Type,
+        input: Type,
+    ) -> TypeResult<(Type, Type)> {
+        let k_fun = self.get_kind(func.clone())?;
+        let k_input = self.get_kind(input)?;
+
+        // Kinds themselves don't have kinds, so there is nothing to constrain
+        if k_fun == Type::NoKind || k_input == Type::NoKind {
+            return Ok((Type::NoKind, Type::NoKind));
+        }
+
+        let k_ret = self.fresh_kind();
+        let expected = Type::create_lambda(k_input, k_ret);
+        self.expect_kind(&func, &k_fun, &expected)?;
+
+        Ok((k_fun, expected))
+    }
 
-        (k_fun, Type::create_lambda(k_input, k_ret.clone()))
+    // Unification would catch kinds which clearly can't match as well,
+    // but it wouldn't know which type they belong to
+    fn expect_kind(self: &Self, ty: &Type, found: &Type, expected: &Type) -> TypeResult<()> {
+        if found.differences(expected).is_empty() {
+            Ok(())
+        } else {
+            Err(TypeError::KindMismatch(
+                ty.clone(),
+                expected.clone(),
+                found.clone(),
+            ))
+        }
     }
 
     // Matching is like unification but variables can only be bound on 1 side
     pub fn match_types(self: &mut Self, left: Type, right: Type) -> TypeResult<Substitution> {
+        self.trace(|| TraceEvent::Match(left.clone(), right.clone()));
+        self.recorded(StepKind::Match, left, right, TypeContext::match_step)
+    }
+
+    fn match_step(self: &mut Self, left: Type, right: Type) -> TypeResult<Substitution> {
         match (&left, &right) {
             (left, right) if left == right => Ok(Substitution::new()),
-            (Type::NoKind, _) => Ok(Substitution::new()),
-            (_, Type::NoKind) => Ok(Substitution::new()),
+            // The error has already been reported
+            (Type::Error, _) | (_, Type::Error) => Ok(Substitution::new()),
             (
                 Type::Constructor(VarName {
                     name: name_left,
@@ -471,7 +2576,7 @@ impl TypeContext {
                     kind: kind_right,
                 }),
             ) if name_right == name_left => {
-                self.match_types(*kind_left.clone(), *kind_right.clone())
+                self.match_types(kind_left.as_ref().clone(), kind_right.as_ref().clone())
             }
             (scheme, other) if scheme.is_scheme() => {
                 let instantiated = self.instantiate(scheme);
@@ -481,14 +2586,26 @@ impl TypeContext {
                 let instantiated = self.instantiate(scheme);
                 self.match_types(other.clone(), instantiated)
             }
-            (Type::Variable(var), right) => {
-                self.bind_type_variable(var.name.clone(), Some(*var.kind.clone()), right.clone())
-            }
+            // Wildcards are the only variables on the right which are not rigid
+            (left, Type::Variable(var)) if var.is_wildcard() => self.bind_type_variable(
+                var.name.clone(),
+                Some(var.kind.as_ref().clone()),
+                left.clone(),
+            ),
+            (Type::Variable(var), right) => self.bind_type_variable(
+                var.name.clone(),
+                Some(var.kind.as_ref().clone()),
+                right.clone(),
+            ),
             (Type::TApply(fun_left, input_left), Type::TApply(fun_right, input_right)) => {
-                let constraint_left =
-                    self.constrain_type_application(*fun_left.clone(), *input_left.clone());
-                let constraint_right =
-                    self.constrain_type_application(*fun_right.clone(), *input_right.clone());
+                let constraint_left = self.constrain_type_application(
+                    fun_left.as_ref().clone(),
+                    input_left.as_ref().clone(),
+                )?;
+                let constraint_right = self.constrain_type_application(
+                    fun_right.as_ref().clone(),
+                    input_right.as_ref().clone(),
+                )?;
 
                 let subst2 = self.unify_many(
                     vec![constraint_left.0, constraint_right.0],
@@ -496,12 +2613,12 @@ impl TypeContext {
                 )?;
 
                 let fun_subst = self.match_types(
-                    fun_left.clone().apply_substitution(&subst2),
-                    fun_right.clone().apply_substitution(&subst2),
+                    fun_left.as_ref().clone().apply_substitution(&subst2),
+                    fun_right.as_ref().clone().apply_substitution(&subst2),
                 )?;
                 let input_subst = self.match_types(
-                    input_left.clone().apply_substitution(&subst2),
-                    input_right.clone().apply_substitution(&subst2),
+                    input_left.as_ref().clone().apply_substitution(&subst2),
+                    input_right.as_ref().clone().apply_substitution(&subst2),
                 )?;
 
                 Ok(merge_substitutions(
@@ -516,10 +2633,15 @@ impl TypeContext {
 
     // UNIFY TYPES
     pub fn unify(self: &mut Self, left: Type, right: Type) -> TypeResult<Substitution> {
+        self.trace(|| TraceEvent::Unify(left.clone(), right.clone()));
+        self.recorded(StepKind::Unify, left, right, TypeContext::unify_step)
+    }
+
+    fn unify_step(self: &mut Self, left: Type, right: Type) -> TypeResult<Substitution> {
         match (&left, &right) {
             (left, right) if left == right => Ok(Substitution::new()),
-            (Type::NoKind, _) => Ok(Substitution::new()),
-            (_, Type::NoKind) => Ok(Substitution::new()),
+            // The error has already been reported
+            (Type::Error, _) | (_, Type::Error) => Ok(Substitution::new()),
             (
                 Type::Constructor(VarName {
                     name: name_left,
@@ -529,7 +2651,9 @@ impl TypeContext {
                     name: name_right,
                     kind: kind_right,
                 }),
-            ) if name_right == name_left => self.unify(*kind_left.clone(), *kind_right.clone()),
+            ) if name_right == name_left => {
+                self.unify(kind_left.as_ref().clone(), kind_right.as_ref().clone())
+            }
             (scheme, other) if scheme.is_scheme() => {
                 let instantiated = self.instantiate(scheme);
                 self.unify(instantiated, other.clone())
@@ -538,30 +2662,38 @@ impl TypeContext {
                 let instantiated = self.instantiate(scheme);
                 self.unify(instantiated, other.clone())
             }
-            (Type::Variable(var), right) => {
-                self.bind_type_variable(var.name.clone(), Some(*var.kind.clone()), right.clone())
-            }
-            (left, Type::Variable(var)) => {
-                self.bind_type_variable(var.name.clone(), Some(*var.kind.clone()), left.clone())
-            }
+            (Type::Variable(var), right) => self.bind_type_variable(
+                var.name.clone(),
+                Some(var.kind.as_ref().clone()),
+                right.clone(),
+            ),
+            (left, Type::Variable(var)) => self.bind_type_variable(
+                var.name.clone(),
+                Some(var.kind.as_ref().clone()),
+                left.clone(),
+            ),
             (Type::TApply(fun_left, input_left), Type::TApply(fun_right, input_right)) => {
-                let constraint_left =
-                    self.constrain_type_application(*fun_left.clone(), *input_left.clone());
-                let constraint_right =
-                    self.constrain_type_application(*fun_right.clone(), *input_right.clone());
+                let constraint_left = self.constrain_type_application(
+                    fun_left.as_ref().clone(),
+                    input_left.as_ref().clone(),
+                )?;
+                let constraint_right = self.constrain_type_application(
+                    fun_right.as_ref().clone(),
+                    input_right.as_ref().clone(),
+                )?;
 
                 self.unify_many(
                     vec![
                         constraint_left.0,
                         constraint_right.0,
-                        *fun_left.clone(),
-                        *input_left.clone(),
+                        fun_left.as_ref().clone(),
+                        input_left.as_ref().clone(),
                     ],
                     vec![
                         constraint_left.1,
                         constraint_right.1,
-                        *fun_right.clone(),
-                        *input_right.clone(),
+                        fun_right.as_ref().clone(),
+                        input_right.as_ref().clone(),
                     ],
                 )
             }
@@ -575,59 +2707,193 @@ impl TypeContext {
         types1: Vec<Type>,
         types2: Vec<Type>,
     ) -> TypeResult<Substitution> {
-        match (types1.split_first(), types2.split_first()) {
-            (None, None) => Ok(Substitution::new()),
-            (Some((left, types1)), Some((right, types2))) => {
-                let substitution = self.unify(left.clone(), right.clone())?;
-                let other_substitution = self.unify_many(
-                    Vec::from(types1).apply_substitution(&substitution),
-                    Vec::from(types2).apply_substitution(&substitution),
-                )?;
-                Ok(merge_substitutions(other_substitution, substitution))
+        if types1.len() != types2.len() {
+            return Err(TypeError::DifferentLengths(types1, types2));
+        }
+
+        let mut substitution = Substitution::new();
+
+        for (left, right) in types1.into_iter().zip(types2) {
+            let solved = self.unify(
+                left.apply_substitution(&substitution),
+                right.apply_substitution(&substitution),
+            )?;
+            substitution = merge_substitutions(solved, substitution);
+        }
+
+        Ok(substitution)
+    }
+
+    // Get the kind of a type generating constraints on the ways
+    pub fn get_kind(self: &mut Self, ty: Type) -> TypeResult<Type> {
+        match ty {
+            Type::Scheme { .. } => {
+                let instantiated = self.instantiate(&ty);
+
+                self.get_kind(instantiated)
+            }
+            Type::Constructor(VarName { name: _, kind }) => Ok(Rc::unwrap_or_clone(kind)),
+            Type::Variable(VarName { name: _, kind }) => Ok(Rc::unwrap_or_clone(kind)),
+            Type::ArrowKind => Ok(Type::create_lambda(
+                Type::star(),
+                Type::create_lambda(Type::star(), Type::star()),
+            )),
+            Type::TApply(fun, input) => {
+                let k_input = self.get_kind(input.as_ref().clone())?;
+                let is_arrow = |ty: &Type| *ty == Type::ArrowKind;
+
+                // The arrow is also used to build kinds, which have no kind themselves
+                if k_input == Type::NoKind {
+                    Ok(Type::NoKind)
+                } else if is_arrow(&fun) {
+                    self.expect_kind(&input, &k_input, &Type::star())?;
+                    self.defer_unify(&k_input, &Type::star(), Reason::Kind);
+                    Ok(Type::create_lambda(Type::star(), Type::star()))
+                } else {
+                    // Both sides of an arrow hold values
+                    if let Type::TApply(arrow, _) = fun.as_ref() {
+                        if is_arrow(arrow) {
+                            self.expect_kind(&input, &k_input, &Type::star())?;
+                        }
+                    }
+
+                    let k_ret = self.fresh_kind();
+                    let k_fun = self.get_kind(fun.as_ref().clone())?;
+
+                    let expected = Type::create_lambda(k_input, k_ret.clone());
+                    self.expect_kind(&fun, &k_fun, &expected)?;
+                    self.defer_unify(&k_fun, &expected, Reason::Kind);
+
+                    Ok(k_ret)
+                }
             }
-            _ => Err(TypeError::DifferentLengths(
-                types1.to_vec(),
-                types2.to_vec(),
-            )),
+            Type::NoKind => Ok(Type::NoKind),
+            Type::Error => Ok(Type::star()),
         }
     }
 
-    // Get the kind of a type generating constraints on the ways
-    pub fn get_kind(self: &mut Self, ty: Type) -> Type {
-        match ty {
-            scheme if scheme.is_scheme() => {
-                let instantiated = self.instantiate(&scheme);
+    // Solve the kinds of a type written by the user right away. Nothing else is
+    // going to, since kinds inside schemes don't get substituted
+    fn infer_kinds(self: &mut TypeContext, ty: Type) -> TypeResult {
+        let (variables, body) = match ty {
+            Type::Scheme { variables, ty } => (variables, Rc::unwrap_or_clone(ty)),
+            other => (Vec::new(), other),
+        };
 
-                self.get_kind(instantiated)
-            }
-            Type::Constructor(VarName { name: _, kind }) => *kind.clone(),
-            Type::Variable(VarName { name: _, kind }) => *kind.clone(),
-            Type::ArrowKind => {
-                let k = Type::NoKind;
-                Type::create_lambda(k.clone(), Type::create_lambda(k.clone(), k)).generalize(self)
+        let mut kinds = self.child();
+        let kind = kinds.get_kind(body.clone())?;
+        kinds.expect_kind(&body, &kind, &Type::star())?;
+        kinds.defer_unify(&kind, &Type::star(), Reason::Kind);
+        let substitution = kinds.solve_constraints()?;
+        self.sync(kinds);
+
+        // Solved kinds are closed, so they can go inside nested schemes like the one in
+        // (forall f. f a -> f b) -> c
+        let variables: Vec<_> = variables
+            .into_iter()
+            .map(|var| VarName {
+                kind: Rc::new(Rc::unwrap_or_clone(var.kind).renamed(&substitution)),
+                ..var
+            })
+            .collect();
+        let body = body.renamed(&substitution);
+
+        Ok(if variables.is_empty() {
+            body
+        } else {
+            body.to_scheme(variables)
+        })
+    }
+
+    // Every type in the tree has to be the type of some value. Only the first
+    // problem gets reported, since a badly kinded type shows up in every node above it
+    fn validate_kinds(self: &mut TypeContext, typed: &TypedAst) -> TypeResult<()> {
+        let mut nodes = vec![(typed, self.location)];
+
+        while let Some((node, location)) = nodes.pop() {
+            let location = match &node.node {
+                TypedNode::Located(span, _) => Some(*span),
+                _ => location,
+            };
+
+            let mut kinds = self.child();
+            kinds.location = location;
+            let kind = kinds.get_kind(node.ty.clone()).and_then(|kind| {
+                kinds.expect_kind(&node.ty, &kind, &Type::star())?;
+                kinds.defer_unify(&kind, &Type::star(), Reason::Kind);
+                kinds.solve_constraints()
+            });
+            self.next_id = max(self.next_id, kinds.next_id);
+
+            if let Err(error) = kind {
+                let outer = self.location;
+                self.location = location;
+                let reported = self.report(error);
+                self.location = outer;
+                return reported;
             }
-            Type::TApply(fun, input) => {
-                if *fun == Type::ArrowKind {
-                    // Type::create_lambda(Type::NoKind, Type::NoKind).generalize(self)
-                    Type::NoKind
-                } else {
-                    let k_ret = self.fresh(Type::NoKind);
-                    let k_input = self.get_kind(*input.clone());
-                    let k_fun = self.get_kind(*fun.clone());
 
-                    self.should_unify(&k_fun, &Type::create_lambda(k_input, k_ret.clone()));
+            if let Some(error) = kinds.errors.into_iter().next() {
+                return self.report_located(error);
+            }
 
-                    k_ret
-                }
+            for child in node.children().into_iter().rev() {
+                nodes.push((child, location));
             }
-            Type::NoKind => Type::NoKind,
-            other => panic!("Cannot get kind of type {}", other),
         }
+
+        Ok(())
     }
 
-    // Todo: remove
+    // Give every variable without a kind (eg: the ones written in annotations) a fresh kind variable
     pub fn kind_unkinded(self: &mut Self, ty: Type) -> Type {
-        ty.clone()
+        self.kind_unkinded_with(ty, &mut HashMap::new())
+    }
+
+    fn kind_unkinded_with(self: &mut Self, ty: Type, kinds: &mut HashMap<String, Type>) -> Type {
+        match ty {
+            Type::Variable(var) => Type::Variable(self.kind_var_name(var, kinds)),
+            // The parser assumes constructors have kind *, which isn't true for the likes of List
+            Type::Constructor(var) => match type_constructors().remove(&var.name) {
+                Some(constructor) => constructor,
+                None => Type::Constructor(var),
+            },
+            Type::TApply(fun, input) => {
+                let fun = self.kind_unkinded_with(Rc::unwrap_or_clone(fun), kinds);
+                fun.apply(self.kind_unkinded_with(Rc::unwrap_or_clone(input), kinds))
+            }
+            Type::Scheme { variables, ty } => Type::Scheme {
+                variables: variables
+                    .into_iter()
+                    .map(|var| self.kind_var_name(var, kinds))
+                    .collect(),
+                ty: Rc::new(self.kind_unkinded_with(Rc::unwrap_or_clone(ty), kinds)),
+            },
+            other => other,
+        }
+    }
+
+    // Variables with the same name get the same kind. Quantifiers come before the body,
+    // so kinds written as `forall (f :: * -> *).` reach every use of the variable
+    fn kind_var_name(self: &mut Self, var: VarName, kinds: &mut HashMap<String, Type>) -> VarName {
+        if *var.kind != Type::NoKind {
+            kinds.insert(var.name.clone(), var.kind.as_ref().clone());
+            return var;
+        }
+
+        let kind = match kinds.get(&var.name) {
+            Some(kind) => kind.clone(),
+            None => {
+                let kind = self.fresh_kind();
+                kinds.insert(var.name.clone(), kind.clone());
+                kind
+            }
+        };
+
+        VarName {
+            name: var.name,
+            kind: Rc::new(kind),
+        }
     }
 
     // Bind a type variable to a type
@@ -640,16 +2906,22 @@ impl TypeContext {
         match &ty {
             Type::Variable(other_var) if *other_var.name == var_name => match var_kind {
                 None => Ok(Substitution::new().update(var_name, ty)),
-                Some(kind) => self.unify(*other_var.kind.clone(), kind.clone()),
+                Some(kind) => self.unify(other_var.kind.as_ref().clone(), kind.clone()),
             },
             other => {
-                if ty.is_recursive(&var_name) {
+                // Checked first, since the occurs check recurses over the type
+                let depth = ty.depth();
+
+                if depth > MAX_TYPE_DEPTH {
+                    Err(TypeError::TypeTooDeep(var_name, depth))
+                } else if ty.is_recursive(&var_name) {
                     Err(TypeError::RecursiveType(var_name, ty))
                 } else {
                     match var_kind {
-                        None => Ok(Substitution::new().update(var_name, ty)),
+                        // Kind variables live one level up, where there is nothing left to check
+                        None | Some(Type::NoKind) => Ok(Substitution::new().update(var_name, ty)),
                         Some(var_kind) => {
-                            let k_other = self.get_kind(other.clone());
+                            let k_other = self.get_kind(other.clone())?;
                             let subst = self.unify(k_other, var_kind.clone())?;
                             let map = subst.update(var_name, ty.apply_substitution(&subst));
                             Ok(map)
@@ -661,9 +2933,40 @@ impl TypeContext {
     }
 }
 
+// How many insertions, deletions, replacements and swaps of neighbouring characters
+// it takes to turn one string into another
+fn edit_distance(left: &str, right: &str) -> usize {
+    let left: Vec<char> = left.chars().collect();
+    let right: Vec<char> = right.chars().collect();
+
+    // distances[i][j] is the distance between the first i characters of left and the first j of right
+    let mut distances = vec![vec![0; right.len() + 1]; left.len() + 1];
+
+    for i in 0..=left.len() {
+        for j in 0..=right.len() {
+            distances[i][j] = if i == 0 || j == 0 {
+                i + j
+            } else {
+                let cost = if left[i - 1] == right[j - 1] { 0 } else { 1 };
+                let mut distance = (distances[i - 1][j - 1] + cost)
+                    .min(distances[i - 1][j] + 1)
+                    .min(distances[i][j - 1] + 1);
+
+                if i > 1 && j > 1 && left[i - 1] == right[j - 2] && left[i - 2] == right[j - 1] {
+                    distance = distance.min(distances[i - 2][j - 2] + 1);
+                }
+
+                distance
+            };
+        }
+    }
+
+    distances[left.len()][right.len()]
+}
+
 pub type Substitution = im::HashMap<String, Type>;
 
-fn merge_substitutions(subst1: Substitution, subst2: Substitution) -> Substitution {
+pub fn merge_substitutions(subst1: Substitution, subst2: Substitution) -> Substitution {
     subst2.apply_substitution(&subst1).union(subst1)
 }
 
@@ -687,26 +2990,21 @@ pub trait Substituable {
 
 impl<T: Substituable + Clone> Substituable for Vec<T> {
     fn free_variables(self: &Self) -> HashSet<VarName> {
-        self.iter()
-            .flat_map(|ty| ty.clone().free_variables())
-            .collect()
+        self.iter().flat_map(|ty| ty.free_variables()).collect()
     }
 
     fn apply_substitution(self: Self, substitution: &Substitution) -> Self {
-        let mut result = Vec::new();
-
-        for ty in self {
-            result.push(ty.clone().apply_substitution(substitution))
-        }
-        result
+        self.into_iter()
+            .map(|ty| ty.apply_substitution(substitution))
+            .collect()
     }
 }
 
 impl Substituable for TypeConstraint {
     fn free_variables(self: &Self) -> HashSet<VarName> {
         let (left, right) = match self {
-            TypeConstraint::Match(left, right) => (left, right),
-            TypeConstraint::Unify(left, right) => (left, right),
+            TypeConstraint::Match(left, right, _) => (left, right),
+            TypeConstraint::Unify(left, right, _) => (left, right),
         };
 
         left.free_variables().union(right.free_variables())
@@ -714,45 +3012,42 @@ impl Substituable for TypeConstraint {
 
     fn apply_substitution(self: Self, substitution: &Substitution) -> Self {
         match self {
-            TypeConstraint::Match(left, right) => TypeConstraint::Match(
+            TypeConstraint::Match(left, right, origin) => TypeConstraint::Match(
                 left.apply_substitution(substitution),
                 right.apply_substitution(substitution),
+                origin,
             ),
-            TypeConstraint::Unify(left, right) => TypeConstraint::Unify(
+            TypeConstraint::Unify(left, right, origin) => TypeConstraint::Unify(
                 left.apply_substitution(substitution),
                 right.apply_substitution(substitution),
+                origin,
             ),
         }
     }
 }
 
+// Covers environments too, since they are the same kind of map
 impl Substituable for Substitution {
     fn free_variables(self: &Substitution) -> HashSet<VarName> {
-        self.values()
-            .flat_map(|ty| ty.clone().free_variables())
-            .collect()
+        self.values().flat_map(|ty| ty.free_variables()).collect()
     }
 
+    // Only the entries the substitution changes get replaced, so the rest stays shared
+    // with whatever else still holds the old map
     fn apply_substitution(self: Self, substitution: &Substitution) -> Substitution {
-        self.iter()
-            .map(|(key, ty)| (key.clone(), ty.clone().apply_substitution(substitution)))
-            .collect()
-    }
-}
+        if substitution.is_empty() {
+            return self;
+        }
 
-impl Substituable for TypeEnv {
-    fn free_variables(self: &Self) -> HashSet<VarName> {
-        self.values()
-            .flat_map(|ty| ty.clone().free_variables())
-            .collect()
-    }
+        let mut result = self.clone();
 
-    fn apply_substitution(self: Self, substitution: &Substitution) -> TypeEnv {
-        HashMap::from_iter(
-            self.iter().map(|(key, scheme)| {
-                (key.clone(), scheme.clone().apply_substitution(substitution))
-            }),
-        )
+        for (key, ty) in self.iter() {
+            if let Some(new_type) = ty.substituted(substitution) {
+                result.insert(key.clone(), new_type);
+            }
+        }
+
+        result
     }
 }
 
@@ -787,26 +3082,893 @@ impl Substituable for Type {
     }
 
     fn apply_substitution(self: Type, substitution: &Substitution) -> Type {
-        match &self {
-            Type::Variable(VarName { name, kind: _ }) => match substitution.get(name) {
-                Some(new_type) => new_type.clone(),
-                None => self,
-            },
-            Type::TApply(fun, input) => (*fun.clone())
-                .apply_substitution(substitution)
-                .apply(input.clone().apply_substitution(substitution)),
-            _ => self,
+        if substitution.is_empty() {
+            return self;
+        }
+
+        self.substituted(substitution).unwrap_or(self)
+    }
+}
+
+// Turn every kind nothing decided on into *
+fn default_kinds(typed: &TypedAst) -> Substitution {
+    let mut defaults = Substitution::new();
+
+    for var in typed.free_variables() {
+        if *var.kind == Type::NoKind {
+            let kinded = VarName {
+                kind: Rc::new(Type::star()),
+                ..var.clone()
+            };
+            defaults.insert(var.name, Type::Variable(kinded));
+        } else {
+            for kind_var in var.kind.free_variables() {
+                defaults.insert(kind_var.name, Type::star());
+            }
+        }
+    }
+
+    defaults
+}
+
+// Infer an expression and solve everything, leaving behind a fully substituted tree
+fn check(
+    mut context: TypeContext,
+    expression: Ast,
+) -> TypeResult<(TypedAst, TypeContext, Substitution)> {
+    let typed = context.infer(expression)?;
+    let subst = context.solve_constraints()?;
+    let typed = typed.apply_substitution(&subst);
+
+    let (typed, subst) = if context.strict_kinds {
+        let defaults = default_kinds(&typed);
+        let typed = typed.apply_substitution(&defaults);
+        context.validate_kinds(&typed)?;
+
+        (typed, merge_substitutions(defaults, subst))
+    } else {
+        (typed, subst)
+    };
+
+    if let Some(skolem) = typed.escaped_skolem() {
+        context.report(TypeError::EscapedSkolem(skolem))?;
+    }
+
+    let comparisons = mem::take(&mut context.comparisons);
+    let mut reported = Vec::new();
+
+    for (ty, span) in comparisons {
+        let ty = ty.apply_substitution(&subst);
+
+        // Annotation variables promise to work for any type, functions included.
+        // A use of a let comparing more than one of its arguments only gets reported once
+        let comparable = !ty.contains_function() && ty.skolems().is_empty();
+        if !comparable && !reported.contains(&span) {
+            reported.push(span);
+            context.report_located(LocatedError {
+                error: TypeError::NotEquatable(ty),
+                span,
+                reason: None,
+                types: None,
+            })?;
         }
     }
+
+    Ok((typed, context, subst))
 }
 
 // ACTUAL FUNCTION FOR GETTING THE TYPE OF AN EXPRESSION
 pub fn get_type_of(expression: Ast) -> TypeResult {
-    let mut context = TypeContext::new();
-    let resulting_type = context.infer(expression)?;
-    let subst = context.solve_constraints()?;
+    get_type_and_notes(expression).map(|(ty, _)| ty)
+}
+
+// Same as get_type_of but also returns the things we found out along the way
+pub fn get_type_and_notes(expression: Ast) -> TypeResult<(Type, Vec<Note>)> {
+    get_type_and_notes_in(TypeContext::new(), expression)
+}
+
+// Same as get_type_and_notes but starting from a given context
+pub fn get_type_and_notes_in(
+    context: TypeContext,
+    expression: Ast,
+) -> TypeResult<(Type, Vec<Note>)> {
+    let (typed, context, subst) = check(context, expression)?;
+
+    Ok(summarize(typed, context, subst))
+}
+
+// Like get_type_and_notes, but keeps going after errors and returns all of them
+pub fn get_type_or_errors(expression: Ast) -> Result<(Type, Vec<Note>), Vec<LocatedError>> {
+    get_type_or_errors_in(TypeContext::new(), expression)
+}
+
+pub fn get_type_or_errors_in(
+    context: TypeContext,
+    expression: Ast,
+) -> Result<(Type, Vec<Note>), Vec<LocatedError>> {
+    let context = context.with_error_recovery();
+    let (typed, context, subst) = check(context, expression).map_err(|error| {
+        vec![LocatedError {
+            error,
+            span: None,
+            reason: None,
+            types: None,
+        }]
+    })?;
+
+    if context.errors.is_empty() {
+        Ok(summarize(typed, context, subst))
+    } else {
+        Err(context.errors)
+    }
+}
+
+// The type of a checked expression and the notes collected while checking it
+fn summarize(typed: TypedAst, mut context: TypeContext, subst: Substitution) -> (Type, Vec<Note>) {
+    let mut notes: Vec<_> = context
+        .wildcards
+        .iter()
+        .map(|(offset, ty)| Note::Wildcard(*offset, ty.clone().apply_substitution(&subst)))
+        .collect();
+
+    for hole in context.holes.clone().apply_substitution(&subst) {
+        let fits = context.fitting_bindings(&hole.environment, &hole.ty);
+        notes.push(Note::Hole(hole.offset, hole.name, hole.ty, fits));
+    }
+
+    // The whole program isn't inside of anything, so all it created can be generalized
+    context.level = 0;
+    let (variables, ty) = match typed.ty.generalize(&context) {
+        Type::Scheme { variables, ty } => (Some(variables), Rc::unwrap_or_clone(ty)),
+        ty => (None, ty),
+    };
+
+    // The variables of the type are named together with the ones in the notes, so the same
+    // variable gets the same name everywhere
+    let mut shown = vec![&ty];
+    shown.extend(notes.iter().flat_map(Note::types));
+    let (order, renaming) = stable_renaming(&shown);
+
+    let notes = notes
+        .into_iter()
+        .map(|note| note.map_types(|ty| ty.renamed(&renaming)))
+        .collect();
+    let ty = ty.renamed(&renaming);
+
+    let ty = match variables {
+        Some(mut variables) => {
+            // Kind variables get quantified before the variables using them
+            let position = |var: &VarName| order.iter().position(|found| found.name == var.name);
+            variables.sort_by_key(|var| (*var.kind != Type::NoKind, position(var)));
+
+            let variables = variables
+                .into_iter()
+                .map(|var| match renaming.get(&var.name) {
+                    Some(Type::Variable(renamed)) => renamed.clone(),
+                    _ => var,
+                })
+                .collect();
+
+            ty.to_scheme(variables)
+        }
+        None => ty,
+    };
+
+    (ty, notes)
+}
+
+// Annotate every node of an expression with its solved type
+pub fn get_typed_ast(expression: Ast) -> TypeResult<TypedAst> {
+    get_typed_ast_in(TypeContext::new(), expression)
+}
+
+pub fn get_typed_ast_in(context: TypeContext, expression: Ast) -> TypeResult<TypedAst> {
+    check(context, expression).map(|(typed, _, _)| with_stable_names(typed))
+}
+
+// Get the type of the innermost expression containing a byte offset
+pub fn type_at(program: &str, offset: usize) -> Option<Type> {
+    let expression = parse_expression(program).ok()?;
+    let (typed, _, _) = check(TypeContext::new(), expression).ok()?;
+    let typed = with_stable_names(typed);
+
+    typed.located_at(offset).map(|node| node.ty.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable(name: &str) -> VarName {
+        VarName {
+            name: name.to_string(),
+            kind: Rc::new(Type::star()),
+        }
+    }
+
+    // The leftover variable gets the first name, which the scheme already quantifies over
+    #[test]
+    fn stable_names_dont_get_captured() {
+        let quantified = variable("a");
+        let ty = Type::create_lambda(
+            Type::Variable(quantified.clone()),
+            Type::Variable(variable("t5")),
+        )
+        .to_scheme(vec![quantified]);
+        let renamed = ty.clone().renamed(&stable_names(&[&ty]));
+
+        assert_eq!(renamed.to_string(), "forall a'. a' -> a");
+    }
+
+    // The message and the notes of an error name the same variable the same way
+    #[test]
+    fn errors_share_names_with_their_notes() {
+        let expression = parse_expression("show >> length").unwrap();
+        let errors = get_type_or_errors(expression).unwrap_err();
+        let error = &errors[0];
+
+        assert_eq!(
+            error.message(Locale::English),
+            "Cannot unify type\n    String\nwith type\n    List a"
+        );
+        assert_eq!(
+            error.notes(Locale::English)[0],
+            "String vs List a inside b -> _"
+        );
+    }
+
+    // Only variables the checker made up get renamed, the ones from annotations are shown
+    // the way they were written
+    #[test]
+    fn errors_keep_written_variable_names() {
+        let expression = parse_expression("(\\x -> x) :: forall (f :: *) a. f a -> f a").unwrap();
+        let errors = get_type_or_errors(expression).unwrap_err();
+
+        assert_eq!(
+            errors[0].message(Locale::English),
+            "Type\n    f\nhas kind *, but kind a -> b is needed here"
+        );
+    }
+
+    // Holes and the type of the program name the same variable the same way
+    #[test]
+    fn notes_share_names_with_the_type() {
+        let expression = parse_expression("\\f x -> f (?h x)").unwrap();
+        let (ty, notes) = get_type_and_notes(expression).unwrap();
+
+        assert_eq!(ty.to_string(), "forall a b c. (a -> b) -> c -> b");
+        match &notes[..] {
+            [Note::Hole(_, _, hole, _)] => assert_eq!(hole.to_string(), "c -> a"),
+            _ => panic!("Expected a single hole, got {:?}", notes),
+        }
+    }
+
+    // Programs using the builtin equality, and whether they should be rejected for comparing
+    // functions
+    const COMPARISONS: &[(&str, bool)] = &[
+        ("1 == 2", false),
+        ("cons \"a\" nil /= nil", false),
+        ("add == add", true),
+        ("neq (cons add nil) nil", true),
+        ("\\f -> f == f", false),
+        ("let eq = \\x y -> x in eq add add", false),
+        ("(\\eq -> eq add add) (\\x y -> true)", false),
+        ("let same = \\x y -> x == y in same add add", true),
+        ("let same = \\x y -> x == y in same 1 2", false),
+        ("let same = eq in same add add", true),
+        ("(\\x y -> x == y) :: forall a. a -> a -> Boolean", true),
+    ];
+
+    // Only the comparisons which could end up comparing functions get rejected
+    #[test]
+    fn only_comparing_functions_is_rejected() {
+        for (source, rejected) in COMPARISONS {
+            match (get_type_of(parse_expression(source).unwrap()), rejected) {
+                (Err(TypeError::NotEquatable(_)), true) | (Ok(_), false) => {}
+                (result, _) => panic!("Checking {} gave {:?}", source, result),
+            }
+        }
+    }
+
+    // Annotations abstracting over type constructors, and the types they should end up with
+    const HIGHER_KINDED: &[(&str, &str)] = &[
+        (
+            "(\\map f x -> map f x) :: forall f a b. ((a -> b) -> f a -> f b) -> (a -> b) -> f a -> f b",
+            "forall a b (c :: * -> *). ((a -> b) -> c a -> c b) -> (a -> b) -> c a -> c b",
+        ),
+        (
+            "(\\bind pure x -> bind x pure) :: forall m a. (m a -> (a -> m a) -> m a) -> (a -> m a) -> m a -> m a",
+            "forall (a :: * -> *) b. (a b -> (b -> a b) -> a b) -> (b -> a b) -> a b -> a b",
+        ),
+        (
+            "let map = (\\f x -> x) :: forall f a. (a -> a) -> f a -> f a in map (add 1) (pure 1)",
+            "Effect Number",
+        ),
+        (
+            "(\\map -> map show (cons 1 nil)) :: (forall f a b. (a -> b) -> f a -> f b) -> List String",
+            "(forall (f :: * -> *) a b. (a -> b) -> f a -> f b) -> List String",
+        ),
+    ];
+
+    // Variables applied to arguments in annotations get kinds which take them, even with
+    // strict kinds and inside nested schemes
+    #[test]
+    fn applied_variables_get_higher_kinds() {
+        for (source, expected) in HIGHER_KINDED {
+            let expression = parse_expression(source).unwrap();
+            let context = TypeContext::new().with_strict_kinds();
+            let (ty, _) = get_type_and_notes_in(context, expression).unwrap();
+
+            assert_eq!(ty.to_string(), *expected, "Checking {}", source);
+        }
+    }
+
+    // Uses of a quantified variable get the kind written on it, instead of a fresh one
+    #[test]
+    fn written_kinds_are_honored() {
+        let check = |source: &str| get_type_of(parse_expression(source).unwrap());
+
+        assert_eq!(
+            check("(\\x -> x) :: forall (f :: * -> *) a. f a -> f a")
+                .unwrap()
+                .to_string(),
+            "forall (a :: * -> *) b. a b -> a b"
+        );
+        for source in [
+            "(\\x -> x) :: forall (f :: * -> *). f -> f",
+            "(\\x -> x) :: forall (f :: *) a. f a -> f a",
+        ] {
+            match check(source) {
+                Err(TypeError::KindMismatch(_, _, _)) => {}
+                result => panic!("Expected {} to have a kind error, got {:?}", source, result),
+            }
+        }
+    }
+
+    // Kinds nothing decided on stay variables, unless strict kinds default them to *
+    #[test]
+    fn strict_kinds_default_to_star() {
+        let source = "let g = (\\x -> x) :: forall f a. f a -> f a in g";
+        let check = |context: TypeContext| {
+            let expression = parse_expression(source).unwrap();
+            let (ty, _) = get_type_and_notes_in(context, expression).unwrap();
+
+            ty.to_string()
+        };
+
+        assert_eq!(
+            check(TypeContext::new()),
+            "forall c (a :: c -> *) (b :: c). a b -> a b"
+        );
+        assert_eq!(
+            check(TypeContext::new().with_strict_kinds()),
+            "forall (a :: * -> *) b. a b -> a b"
+        );
+    }
+
+    #[test]
+    fn arrows_missing_their_result_print_as_sections() {
+        assert_eq!(
+            Type::ArrowKind.apply(Type::number()).to_string(),
+            "(Number ->)"
+        );
+    }
+
+    // Variables in the types of foreign imports get their kinds from how they're applied
+    #[test]
+    fn foreign_types_get_their_kinds_inferred() {
+        let expression =
+            parse_expression("foreign import fmap :: (a -> b) -> f a -> f b in fmap").unwrap();
+        let context = TypeContext::new().with_strict_kinds();
+        let (ty, _) = get_type_and_notes_in(context, expression).unwrap();
+
+        assert_eq!(
+            ty.to_string(),
+            "forall a b (c :: * -> *). (a -> b) -> c a -> c b"
+        );
+    }
+
+    // Polymorphic arguments get instantiated at every use, and only polymorphic enough
+    // functions can be passed in for them
+    #[test]
+    fn rank_n_arguments_stay_polymorphic() {
+        let check = |source: &str| get_type_of(parse_expression(source).unwrap());
+        let function = "((\\f -> add (f 1) (length (f nil))) :: (forall a. a -> a) -> Number)";
+
+        assert_eq!(
+            check(function).unwrap().to_string(),
+            "(forall a. a -> a) -> Number"
+        );
+        assert_eq!(
+            check(&format!("{} (\\x -> x)", function))
+                .unwrap()
+                .to_string(),
+            "Number"
+        );
+        assert!(check(&format!("{} (add 1)", function)).is_err());
+        assert!(check("\\f -> add (f 1) (length (f nil))").is_err());
+    }
+
+    // Expected types get pushed down into branches and lambdas, so mismatches point at the
+    // innermost expression causing them
+    #[test]
+    fn mismatches_point_at_the_innermost_expression() {
+        let span = |source: &'static str| {
+            let context = TypeContext::new().with_eager_solving();
+            let errors =
+                get_type_or_errors_in(context, parse_expression(source).unwrap()).unwrap_err();
+            let span = errors[0].span.unwrap();
+
+            &source[span.start..span.end]
+        };
+
+        assert_eq!(span("add 1 (if true then 2 else \"a\")"), "\"a\"");
+        assert_eq!(
+            span("(\\x -> if x then 1 else \"a\") :: Boolean -> Number"),
+            "\"a\""
+        );
+        assert_eq!(span("add 1 ((\\x -> \"a\") 2)"), "(\\x -> \"a\") 2");
+    }
+
+    // Variables the context didn't create count as the outermost level, so lets never
+    // generalize them
+    #[test]
+    fn host_variables_are_never_generalized() {
+        let context = TypeContext::builder()
+            .with_binding("x", Type::Variable(variable("host")))
+            .build();
+        let expression = parse_expression("let y = x in let z = \\w -> y in z").unwrap();
+        let (ty, _) = get_type_and_notes_in(context, expression).unwrap();
+
+        assert_eq!(ty.to_string(), "forall a. a -> b");
+    }
+
+    // Calls don't count as values, so the value restriction keeps what they give back
+    // monomorphic, while lambdas still get generalized
+    #[test]
+    fn generalization_policies_pick_which_lets_generalize() {
+        let check = |source: &str, generalization: Generalization| {
+            let context = TypeContext::new().with_generalization(generalization);
+
+            get_type_or_errors_in(context, parse_expression(source).unwrap()).is_ok()
+        };
+        let call = "let f = (\\x -> x) (\\y -> y) in let a = f 1 in f \"a\"";
+        let lambda = "let f = \\x -> x in let a = f 1 in f \"a\"";
+
+        assert!(check(call, Generalization::All));
+        assert!(!check(call, Generalization::Values));
+        assert!(check(lambda, Generalization::Values));
+        assert!(!check(lambda, Generalization::Never));
+    }
+
+    #[test]
+    fn options_round_trip() {
+        let options = TypeCheckOptions {
+            eager_solving: true,
+            error_recovery: false,
+            strict_kinds: true,
+            generalization: Generalization::Values,
+        };
+
+        assert_eq!(TypeContext::new().with_options(options).options(), options);
+        assert_eq!(Generalization::parse("never"), Some(Generalization::Never));
+        assert_eq!(Generalization::parse("some"), None);
+    }
+
+    // Lets comparing their arguments can only be used on things which can be compared
+    #[test]
+    fn generalized_lets_keep_their_comparisons() {
+        let check = |source: &str| get_type_of(parse_expression(source).unwrap());
+
+        match check("let f = \\x y -> x == y in f add add") {
+            Err(TypeError::NotEquatable(_)) => {}
+            result => panic!("Expected the comparison to be rejected, got {:?}", result),
+        }
+        assert_eq!(
+            check("let f = \\x y -> x == y in f 1 2")
+                .unwrap()
+                .to_string(),
+            "Boolean"
+        );
+    }
+
+    #[test]
+    fn characters_are_chars() {
+        let expression = parse_expression("cons 'a' nil").unwrap();
+
+        assert_eq!(get_type_of(expression).unwrap().to_string(), "List Char");
+    }
+
+    // Annotations can make a type less general, but never more general than what's inferred
+    #[test]
+    fn annotations_are_checked_by_subsumption() {
+        let check = |source: &str| get_type_of(parse_expression(source).unwrap());
+
+        assert_eq!(
+            check("(\\x -> x) :: forall a. a -> a").unwrap().to_string(),
+            "forall a. a -> a"
+        );
+        assert_eq!(
+            check("((\\x -> x) :: a -> a) 1").unwrap().to_string(),
+            "Number"
+        );
+        assert_eq!(
+            check("(\\x -> x) :: Number -> Number").unwrap().to_string(),
+            "Number -> Number"
+        );
+        match check("(\\x -> 1) :: a -> a") {
+            Err(TypeError::UnificationError(_, _)) => {}
+            result => panic!(
+                "Expected the annotation to be too general, got {:?}",
+                result
+            ),
+        }
+    }
+
+    #[test]
+    fn skolems_cant_escape_their_annotation() {
+        let expression = parse_expression("\\y -> (y :: a)").unwrap();
+
+        match get_type_of(expression) {
+            Err(TypeError::EscapedSkolem(_)) => {}
+            result => panic!("Expected a to escape, got {:?}", result),
+        }
+    }
+
+    // The bindings which would fit in the only hole of a program, in the order they're listed
+    fn hole_candidates(source: &str) -> Vec<String> {
+        let (_, notes) = get_type_and_notes(parse_expression(source).unwrap()).unwrap();
+
+        match &notes[..] {
+            [Note::Hole(_, _, _, candidates)] => {
+                candidates.iter().map(|(name, _)| name.clone()).collect()
+            }
+            _ => panic!("Expected a single hole in {}, got {:?}", source, notes),
+        }
+    }
+
+    // Holes get typed from how they're used, without stopping inference
+    #[test]
+    fn holes_get_reported_with_their_type() {
+        let expression = parse_expression("add _ (?h :: Number)").unwrap();
+        let (ty, notes) = get_type_and_notes(expression).unwrap();
+
+        assert_eq!(ty.to_string(), "Number");
+        match &notes[..] {
+            [Note::Hole(_, None, first, _), Note::Hole(_, Some(name), second, _)] => {
+                assert_eq!(first.to_string(), "Number");
+                assert_eq!(name, "h");
+                assert_eq!(second.to_string(), "Number");
+            }
+            _ => panic!("Expected two holes, got {:?}", notes),
+        }
+    }
+
+    // Anything would fit in a hole nothing is known about, so those don't list any
+    #[test]
+    fn holes_list_bindings_which_fit() {
+        assert_eq!(hole_candidates("?h :: Boolean"), vec!["false", "true"]);
+        assert!(hole_candidates("\\x -> ?h").is_empty());
+    }
+
+    // Bindings fitting without instantiating anything come first, then it goes by name,
+    // keeping the first 5
+    #[test]
+    fn hole_candidates_are_ranked() {
+        assert_eq!(
+            hole_candidates("let xs = cons 1 nil in ?h :: List Number"),
+            vec!["xs", "nil"]
+        );
+        assert_eq!(
+            hole_candidates(
+                "let f = 1 in let e = 1 in let d = 1 in let c = 1 in let b = 1 in let a = 1 in ?h :: Number"
+            ),
+            vec!["a", "b", "c", "d", "e"]
+        );
+    }
 
-    Ok(resulting_type
-        .apply_substitution(&subst)
-        .generalize(&context))
+    // Asking whether types would unify leaves the context the way it was
+    #[test]
+    fn unifier_doesnt_change_the_context() {
+        let mut context = TypeContext::new();
+        let variable = context.fresh_star();
+
+        assert!(context.can_unify(&variable, &Type::number()));
+        assert!(!context.can_unify(&Type::string(), &Type::number()));
+        assert_eq!(
+            context
+                .unifier(&variable, &Type::number())
+                .unwrap()
+                .get(&variable.to_string())
+                .map(|ty| ty.to_string()),
+            Some("Number".to_string())
+        );
+        assert!(context.current_substitution().is_empty());
+        assert!(context.pending_constraints().is_empty());
+    }
+
+    // Every stage gets called with the result of the one before it
+    #[test]
+    fn pipelines_are_calls() {
+        let expression = parse_expression("1 |> add 2 |> show").unwrap();
+
+        assert_eq!(get_type_of(expression).unwrap().to_string(), "String");
+        assert!(get_type_of(parse_expression("show |> 1").unwrap()).is_err());
+    }
+
+    // Both directions run the function on the side the arrows point away from first
+    #[test]
+    fn compositions_run_in_the_order_they_point() {
+        let check = |source: &str| get_type_of(parse_expression(source).unwrap());
+
+        assert_eq!(
+            check("add 1 >> show").unwrap().to_string(),
+            "Number -> String"
+        );
+        assert_eq!(
+            check("show << add 1").unwrap().to_string(),
+            "Number -> String"
+        );
+        assert!(check("show >> add 1").is_err());
+    }
+
+    // Negating a call negates its result, instead of the function being called
+    #[test]
+    fn negations_cover_whole_calls() {
+        let expression = parse_expression("\\x -> -add x 1").unwrap();
+
+        assert_eq!(
+            get_type_of(expression).unwrap().to_string(),
+            "Number -> Number"
+        );
+    }
+
+    // Every branch, otherwise included, has to give back the same type
+    #[test]
+    fn multi_way_if_branches_agree() {
+        let check = |source: &str| get_type_of(parse_expression(source).unwrap());
+
+        assert_eq!(
+            check("\\x -> if | x == 1 -> \"one\" | otherwise -> show x")
+                .unwrap()
+                .to_string(),
+            "Number -> String"
+        );
+        assert!(check("if | true -> 1 | otherwise -> \"a\"").is_err());
+    }
+
+    // Types of values which are missing arguments get a kind error saying so, once
+    #[test]
+    fn missing_type_arguments_are_kind_errors() {
+        let sources = [
+            "1 :: List",
+            "(\\x -> x) :: List -> Number",
+            "(\\x -> x) :: Number -> List",
+            "foreign import f :: List in f",
+        ];
+
+        for source in sources {
+            for context in [TypeContext::new(), TypeContext::new().with_strict_kinds()] {
+                let expression = parse_expression(source).unwrap();
+                let errors = get_type_or_errors_in(context, expression).unwrap_err();
+
+                match &errors[..] {
+                    [error] => {
+                        assert_eq!(error.error.code(), "E0007", "Checking {}", source);
+                        assert_eq!(
+                            error.error.hints(Locale::English),
+                            vec!["List is missing an argument"]
+                        );
+                    }
+                    _ => panic!("Checking {} gave {} errors", source, errors.len()),
+                }
+            }
+        }
+    }
+
+    // Mismatches caused by one already reported don't get reported again, while the same
+    // mistake made twice does. Solving eagerly like the cli, so constraints keep apart
+    #[test]
+    fn follow_on_errors_are_dropped() {
+        let count = |source: &str| {
+            let context = TypeContext::new().with_eager_solving();
+
+            match get_type_or_errors_in(context, parse_expression(source).unwrap()) {
+                Ok(_) => 0,
+                Err(errors) => errors.len(),
+            }
+        };
+
+        assert_eq!(count("(\\x -> add x 1) :: forall a. a -> a"), 1);
+        assert_eq!(count("1 :: List"), 1);
+        assert_eq!(count("if 1 then 2 else \"a\""), 2);
+        assert_eq!(count("add (if 1 then 2 else 3) (if 1 then 2 else 3)"), 2);
+    }
+
+    // Swapping two neighbouring characters is a single typo
+    #[test]
+    fn edit_distance_counts_swaps_once() {
+        assert_eq!(edit_distance("length", "length"), 0);
+        assert_eq!(edit_distance("lenght", "length"), 1);
+        assert_eq!(edit_distance("valeu", "value"), 1);
+        assert_eq!(edit_distance("fo", "foo"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    // The candidates of the error for the only variable which isn't in scope
+    fn not_in_scope_candidates(source: &str) -> Vec<String> {
+        match get_type_of(parse_expression(source).unwrap()) {
+            Err(TypeError::NotInScope(_, candidates)) => candidates,
+            result => panic!("Expected {} to not be in scope, got {:?}", source, result),
+        }
+    }
+
+    // Longer names are allowed more typos, one for every three characters
+    #[test]
+    fn similar_names_get_suggested() {
+        assert_eq!(not_in_scope_candidates("lenght"), vec!["length"]);
+        assert_eq!(
+            not_in_scope_candidates("let fo = 1 in let foo = 2 in fooo"),
+            vec!["foo"]
+        );
+        assert!(not_in_scope_candidates("ab").is_empty());
+        assert!(
+            not_in_scope_candidates("let element = 1 in elemnet").contains(&"element".to_string())
+        );
+    }
+
+    // Statements whose result gets thrown away bind it to _, which can't be referred to
+    #[test]
+    fn underscores_are_never_suggested() {
+        let expression = parse_expression("do { _ <- print \"a\"; print (show x) }").unwrap();
+        let errors = get_type_or_errors(expression).unwrap_err();
+
+        match &errors[0].error {
+            TypeError::NotInScope(name, candidates) => {
+                assert_eq!(name, "x");
+                assert!(candidates.is_empty(), "Suggested {:?}", candidates);
+            }
+            error => panic!("Expected x to not be in scope, got {:?}", error),
+        }
+    }
+
+    // Solving a let tells whatever is outside of it about the outer variables it ran into
+    #[test]
+    fn nested_lets_solve_outer_variables() {
+        let expression = parse_expression("\\x -> let y = let z = add x 1 in z in x").unwrap();
+
+        assert_eq!(
+            get_type_of(expression).unwrap().to_string(),
+            "Number -> Number"
+        );
+    }
+
+    #[test]
+    fn nested_lets_stay_polymorphic() {
+        let expression = parse_expression("let f = let id = \\x -> x in id in f f 1").unwrap();
+
+        assert_eq!(get_type_of(expression).unwrap().to_string(), "Number");
+    }
+
+    #[test]
+    fn constraints_show_their_reason_and_span() {
+        let mut context = TypeContext::new();
+        context
+            .infer(parse_expression("\\x -> add x 1").unwrap())
+            .unwrap();
+        let constraints: Vec<_> = context
+            .constraints
+            .iter()
+            .map(|constraint| constraint.to_string())
+            .collect();
+
+        assert_eq!(
+            constraints,
+            vec![
+                "t0 ~ Number -- Application at offsets 10-11",
+                "Number ~ Number -- Application at offsets 12-13"
+            ]
+        );
+    }
+
+    // Every constraint, unification and solution gets passed to the tracer as it happens
+    #[test]
+    fn tracers_see_every_step() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorded = events.clone();
+        let context = TypeContext::new()
+            .with_eager_solving()
+            .with_tracer(move |event| recorded.borrow_mut().push(event.to_string()));
+
+        get_type_or_errors_in(context, parse_expression("add 1 2").unwrap()).unwrap();
+
+        let events = events.borrow();
+        assert_eq!(
+            events[..4],
+            [
+                "expression at offsets 0-3 has type Number -> Number -> Number",
+                "constraint Number ~ Number -- Application at offsets 4-5",
+                "unify Number with Number",
+                "solved "
+            ]
+        );
+        assert_eq!(
+            events.last().unwrap(),
+            "expression at offsets 0-7 has type Number"
+        );
+    }
+
+    // Looking for bindings which fit a hole only tries unifications out, like unifying
+    // the Boolean of true with the one of the hole
+    #[test]
+    fn trying_out_unifications_isnt_traced() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorded = events.clone();
+        let context = TypeContext::new()
+            .with_eager_solving()
+            .with_tracer(move |event| recorded.borrow_mut().push(event.to_string()));
+        let expression = parse_expression("?h :: Boolean").unwrap();
+        let (_, notes) = get_type_and_notes_in(context, expression).unwrap();
+
+        assert!(matches!(&notes[..], [Note::Hole(_, _, _, candidates)] if candidates.len() == 2));
+        assert!(events
+            .borrow()
+            .contains(&"unify t0 with Boolean".to_string()));
+        assert!(!events
+            .borrow()
+            .contains(&"unify Boolean with Boolean".to_string()));
+    }
+
+    // Trying out a program which doesn't check leaves the context able to check the next
+    // one as if nothing happened
+    #[test]
+    fn rollback_undoes_a_failed_check() {
+        let mut context = TypeContext::new().with_error_recovery();
+        let snapshot = context.snapshot();
+
+        context
+            .infer(parse_expression("add 1 (lenght \"a\")").unwrap())
+            .unwrap();
+        assert!(!context.errors().is_empty());
+        context.rollback(snapshot);
+
+        assert!(context.errors().is_empty());
+        assert!(context.pending_constraints().is_empty());
+        assert_eq!(context.fresh_star().to_string(), "t0");
+    }
+
+    // The variables cons and nil get instantiated with never leave the let
+    #[test]
+    fn lets_only_pass_up_outer_variables() {
+        let mut context = TypeContext::new();
+        let outer = context.fresh_star();
+        let mut context = context.create_closure("x".to_string(), outer);
+        let expression = parse_expression("let y = cons (add x 1) (cons 2 nil) in y").unwrap();
+        context.infer(expression).unwrap();
+
+        assert_eq!(context.constraints.len(), 1);
+    }
+
+    #[test]
+    fn environment_free_variables_come_from_types() {
+        let mut context = TypeContext::empty();
+        let outer = context.fresh_star();
+        let context = context.create_closure("x".to_string(), outer.clone());
+        let free = context.environment_free_variables();
+
+        assert_eq!(free, HashSet::unit(outer.to_string()));
+        assert!(!free.contains("x"));
+    }
+
+    // y shares the type of x, so it has to stay a Number instead of becoming polymorphic
+    #[test]
+    fn lets_dont_generalize_environment_variables() {
+        let expression = parse_expression("\\x -> let y = x in add y 1").unwrap();
+
+        assert_eq!(
+            get_type_of(expression).unwrap().to_string(),
+            "Number -> Number"
+        );
+    }
 }
@@ -0,0 +1,156 @@
+use super::type_::{Substituable, Substitution, Type, VarName};
+use crate::parser::Span;
+use im::HashSet;
+use std::rc::Rc;
+
+// An expression where every node knows its type
+#[derive(Debug, Clone)]
+pub struct TypedAst {
+    pub ty: Type,
+    pub node: TypedNode,
+}
+
+// Mirrors parser::Ast, except children are typed
+#[derive(Debug, Clone)]
+pub enum TypedNode {
+    Variable(String),
+    Hole(Option<String>),
+    Error(String),
+    FloatLiteral(f64),
+    StringLiteral(String),
+    CharLiteral(char),
+    CustomLiteral(String),
+    If(Box<TypedAst>, Box<TypedAst>, Box<TypedAst>),
+    Let(String, Box<TypedAst>, Box<TypedAst>),
+    FunctionCall(Box<TypedAst>, Box<TypedAst>),
+    Lambda(String, Box<TypedAst>),
+    Annotation(Box<TypedAst>, Type),
+    Foreign(String, Type, Box<TypedAst>),
+    Located(Span, Box<TypedAst>),
+}
+
+impl TypedAst {
+    pub fn new(node: TypedNode, ty: Type) -> TypedAst {
+        TypedAst { ty, node }
+    }
+
+    // The direct subexpressions of this node
+    pub fn children(self: &TypedAst) -> Vec<&TypedAst> {
+        match &self.node {
+            TypedNode::Variable(_)
+            | TypedNode::Hole(_)
+            | TypedNode::Error(_)
+            | TypedNode::FloatLiteral(_)
+            | TypedNode::StringLiteral(_)
+            | TypedNode::CharLiteral(_)
+            | TypedNode::CustomLiteral(_) => vec![],
+            TypedNode::If(condition, left, right) => vec![condition, left, right],
+            TypedNode::Let(_, value, body) => vec![value, body],
+            TypedNode::FunctionCall(function, argument) => vec![function, argument],
+            TypedNode::Lambda(_, body) => vec![body],
+            TypedNode::Annotation(annotated, _) => vec![annotated],
+            TypedNode::Foreign(_, _, body) => vec![body],
+            TypedNode::Located(_, expression) => vec![expression],
+        }
+        .into_iter()
+        .map(|child| &**child)
+        .collect()
+    }
+
+    // Every type in the tree, parents before their children
+    pub fn types(self: &TypedAst) -> Vec<&Type> {
+        let mut types = vec![&self.ty];
+
+        match &self.node {
+            TypedNode::Annotation(_, ty) | TypedNode::Foreign(_, ty, _) => types.push(ty),
+            _ => {}
+        }
+
+        for child in self.children() {
+            types.extend(child.types());
+        }
+
+        types
+    }
+
+    // The same tree with every type in it replaced by what f gives back for it
+    pub fn map_types(self: TypedAst, f: &impl Fn(Type) -> Type) -> TypedAst {
+        let map = |child: Box<TypedAst>| Box::new(child.map_types(f));
+
+        let node = match self.node {
+            TypedNode::If(condition, left, right) => {
+                TypedNode::If(map(condition), map(left), map(right))
+            }
+            TypedNode::Let(name, value, body) => TypedNode::Let(name, map(value), map(body)),
+            TypedNode::FunctionCall(function, argument) => {
+                TypedNode::FunctionCall(map(function), map(argument))
+            }
+            TypedNode::Lambda(name, body) => TypedNode::Lambda(name, map(body)),
+            TypedNode::Annotation(annotated, annotation) => {
+                TypedNode::Annotation(map(annotated), f(annotation))
+            }
+            TypedNode::Foreign(name, ty, body) => TypedNode::Foreign(name, f(ty), map(body)),
+            TypedNode::Located(span, expression) => TypedNode::Located(span, map(expression)),
+            leaf => leaf,
+        };
+
+        TypedAst {
+            ty: f(self.ty),
+            node,
+        }
+    }
+
+    // Annotations may only mention their own skolems inside of themselves
+    pub fn escaped_skolem(self: &TypedAst) -> Option<Type> {
+        self.escaped_skolem_with(&HashSet::new())
+    }
+
+    fn escaped_skolem_with(self: &TypedAst, allowed: &HashSet<String>) -> Option<Type> {
+        let allowed = match &self.node {
+            TypedNode::Annotation(_, annotation) => annotation.skolems().union(allowed.clone()),
+            _ => allowed.clone(),
+        };
+
+        match self
+            .ty
+            .skolems()
+            .into_iter()
+            .find(|name| !allowed.contains(name))
+        {
+            Some(name) => Some(Type::Constructor(VarName {
+                name,
+                kind: Rc::new(Type::star()),
+            })),
+            None => self
+                .children()
+                .into_iter()
+                .find_map(|child| child.escaped_skolem_with(&allowed)),
+        }
+    }
+
+    // Find the innermost located node containing a byte offset
+    pub fn located_at(self: &TypedAst, offset: usize) -> Option<&TypedAst> {
+        let inner = self
+            .children()
+            .into_iter()
+            .find_map(|child| child.located_at(offset));
+
+        match &self.node {
+            TypedNode::Located(span, _) if span.contains(offset) => inner.or(Some(self)),
+            _ => inner,
+        }
+    }
+}
+
+impl Substituable for TypedAst {
+    fn free_variables(self: &Self) -> HashSet<VarName> {
+        self.children()
+            .into_iter()
+            .map(Substituable::free_variables)
+            .fold(self.ty.free_variables(), HashSet::union)
+    }
+
+    fn apply_substitution(self: Self, substitution: &Substitution) -> Self {
+        self.map_types(&|ty| ty.apply_substitution(substitution))
+    }
+}